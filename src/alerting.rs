@@ -0,0 +1,1085 @@
+use crate::grafana::PanelTarget;
+use crate::graylog;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Graylog checks alert conditions once per minute.
+const EVALUATION_INTERVAL: i64 = 60;
+
+/// Label carrying the id of the event definition a rule was converted from,
+/// notification policy routes match on it.
+pub const EVENT_DEFINITION_LABEL: &str = "graylog_event_definition";
+
+/// Alert rules evaluated together every `interval` seconds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RuleGroup {
+    pub title: String,
+    pub interval: i64,
+    pub rules: Vec<AlertRule>,
+}
+
+/// File read by Grafana's alerting provisioning (`provisioning/alerting/*.yaml`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Provisioning {
+    #[serde(rename = "apiVersion")]
+    api_version: i64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<ProvisioningGroup>,
+    #[serde(
+        rename = "contactPoints",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    contact_points: Vec<ProvisioningContactPoint>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    policies: Vec<ProvisioningPolicy>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ProvisioningGroup {
+    #[serde(rename = "orgId")]
+    org_id: i64,
+    name: String,
+    folder: String,
+    interval: String,
+    rules: Vec<AlertRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ProvisioningContactPoint {
+    #[serde(rename = "orgId")]
+    org_id: i64,
+    name: String,
+    receivers: Vec<Receiver>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ProvisioningPolicy {
+    #[serde(rename = "orgId")]
+    org_id: i64,
+    #[serde(flatten)]
+    policy: NotificationPolicy,
+}
+
+/// Body of `PUT /api/v1/provisioning/folder/:folderUid/rule-groups/:group`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiRuleGroup {
+    pub title: String,
+    #[serde(rename = "folderUid")]
+    pub folder_uid: String,
+    pub interval: i64,
+    pub rules: Vec<AlertRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlertRule {
+    uid: String,
+    title: String,
+    condition: String,
+    data: Vec<AlertQuery>,
+    #[serde(rename = "noDataState")]
+    no_data_state: String,
+    #[serde(rename = "execErrState")]
+    exec_err_state: String,
+    #[serde(rename = "for")]
+    pending: String,
+    annotations: BTreeMap<String, String>,
+    labels: BTreeMap<String, String>,
+    #[serde(rename = "folderUID", skip_serializing_if = "Option::is_none")]
+    folder_uid: Option<String>,
+    #[serde(rename = "ruleGroup", skip_serializing_if = "Option::is_none")]
+    rule_group: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct AlertQuery {
+    #[serde(rename = "refId")]
+    ref_id: String,
+    #[serde(rename = "relativeTimeRange", skip_serializing_if = "Option::is_none")]
+    relative_time_range: Option<RelativeTimeRange>,
+    #[serde(rename = "datasourceUid")]
+    datasource_uid: String,
+    model: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RelativeTimeRange {
+    from: i64,
+    to: i64,
+}
+
+/// How the series returned by the datasource query decides whether a rule fires.
+struct Threshold {
+    reducer: &'static str,
+    evaluator: &'static str,
+    value: f64,
+}
+
+/// A contact point with a single receiver, one per Graylog notification.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContactPoint {
+    pub name: String,
+    pub receivers: Vec<Receiver>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Receiver {
+    pub uid: String,
+    pub r#type: String,
+    pub settings: Value,
+}
+
+/// Root of Grafana's notification policy tree.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationPolicy {
+    pub receiver: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<NotificationRoute>,
+    /// Settings we don't touch, kept when the tree is read from Grafana and written back.
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationRoute {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    receiver: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    object_matchers: Vec<Vec<String>>,
+    #[serde(rename = "continue", default)]
+    continue_matching: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeat_interval: Option<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+/// Alerting resources converted from Graylog event definitions and notifications.
+#[derive(Debug, Clone)]
+pub struct EventsConversion {
+    pub groups: Vec<RuleGroup>,
+    pub contact_points: Vec<ContactPoint>,
+    pub routes: Vec<NotificationRoute>,
+    /// Everything which couldn't be expressed in Grafana or only approximately.
+    pub report: Vec<String>,
+}
+
+impl Provisioning {
+    pub fn new(groups: Vec<RuleGroup>, folder: &str) -> Provisioning {
+        Provisioning {
+            api_version: 1,
+            groups: groups
+                .into_iter()
+                .map(|group| ProvisioningGroup {
+                    org_id: 1,
+                    name: group.title,
+                    folder: folder.to_string(),
+                    interval: format!("{}s", group.interval),
+                    rules: group.rules,
+                })
+                .collect(),
+            contact_points: vec![],
+            policies: vec![],
+        }
+    }
+
+    /// Adds contact points and a policy tree. Grafana replaces its whole tree by the provisioned one.
+    pub fn with_contact_points(mut self, contact_points: Vec<ContactPoint>) -> Provisioning {
+        self.contact_points = contact_points
+            .into_iter()
+            .map(|contact_point| ProvisioningContactPoint {
+                org_id: 1,
+                name: contact_point.name,
+                receivers: contact_point.receivers,
+            })
+            .collect();
+        self
+    }
+
+    /// Adds the notification policy tree, Grafana replaces its whole tree by it.
+    pub fn with_policy(mut self, policy: NotificationPolicy) -> Provisioning {
+        self.policies = vec![ProvisioningPolicy { org_id: 1, policy }];
+        self
+    }
+}
+
+impl ApiRuleGroup {
+    pub fn new(group: RuleGroup, folder_uid: &str) -> ApiRuleGroup {
+        let title = group.title;
+        ApiRuleGroup {
+            rules: group
+                .rules
+                .into_iter()
+                .map(|rule| AlertRule {
+                    folder_uid: Some(folder_uid.to_string()),
+                    rule_group: Some(title.clone()),
+                    ..rule
+                })
+                .collect(),
+            title,
+            folder_uid: folder_uid.to_string(),
+            interval: group.interval,
+        }
+    }
+}
+
+impl RuleGroup {
+    /// Converts the alert conditions of every stream into one rule group per stream.
+    /// Streams without supported alert conditions are left out.
+    pub fn create_rule_groups_from_graylog(
+        content_pack: &graylog::ContentPack,
+        datasource_uid: &str,
+        time_field: &str,
+    ) -> Vec<RuleGroup> {
+        content_pack
+            .streams
+            .iter()
+            .map(|stream| RuleGroup {
+                title: stream.title.clone(),
+                interval: EVALUATION_INTERVAL,
+                rules: stream
+                    .alert_conditions
+                    .iter()
+                    .filter_map(|condition| {
+                        AlertRule::create_alert_rule(stream, condition, datasource_uid, time_field)
+                    })
+                    .collect(),
+            })
+            .filter(|group| !group.rules.is_empty())
+            .collect()
+    }
+}
+
+impl AlertRule {
+    fn new(uid: &str, title: String, query: AlertQuery, threshold: Threshold) -> AlertRule {
+        AlertRule {
+            uid: uid.chars().take(40).collect(),
+            title,
+            condition: "C".to_string(),
+            data: vec![
+                query,
+                AlertQuery::expression(
+                    "B",
+                    json!({
+                        "type": "reduce",
+                        "expression": "A",
+                        "reducer": threshold.reducer,
+                        "refId": "B",
+                    }),
+                ),
+                AlertQuery::expression(
+                    "C",
+                    json!({
+                        "type": "threshold",
+                        "expression": "B",
+                        "conditions": [{
+                            "evaluator": {"type": threshold.evaluator, "params": [threshold.value]}
+                        }],
+                        "refId": "C",
+                    }),
+                ),
+            ],
+            // A silent stream is below every lower threshold.
+            no_data_state: if threshold.evaluator == "lt" {
+                "Alerting"
+            } else {
+                "OK"
+            }
+            .to_string(),
+            exec_err_state: "Error".to_string(),
+            pending: "0s".to_string(),
+            annotations: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            folder_uid: None,
+            rule_group: None,
+        }
+    }
+
+    pub fn create_alert_rule(
+        stream: &graylog::Stream,
+        condition: &graylog::AlertCondition,
+        datasource_uid: &str,
+        time_field: &str,
+    ) -> Option<AlertRule> {
+        let parameters = &condition.parameters;
+        // Graylog stores the ids of all matching streams in every message.
+        let query = match parameters.query.as_ref().map(|q| q.trim()) {
+            Some(q) if !q.is_empty() && q != "*" => format!("streams:{} AND ({})", stream.id, q),
+            _ => format!("streams:{}", stream.id),
+        };
+        let window = parameters.time.unwrap_or(1) * 60;
+
+        let (target, threshold) = match condition.r#type {
+            graylog::AlertConditionType::MessageCount => (
+                PanelTarget::new_metric(
+                    &query,
+                    "1m",
+                    "A",
+                    "select field".to_string(),
+                    "count",
+                    time_field,
+                ),
+                Threshold {
+                    reducer: "sum",
+                    evaluator: threshold_evaluator(parameters.threshold_type.as_deref()),
+                    value: parameters.threshold.unwrap_or(0.0),
+                },
+            ),
+            graylog::AlertConditionType::FieldValue => {
+                let (metric, reducer) = match parameters.r#type.as_deref() {
+                    Some("MIN") => ("min", "min"),
+                    Some("MAX") => ("max", "max"),
+                    Some("SUM") => ("sum", "sum"),
+                    function => {
+                        warn!(
+                            "{} of alert condition {} of stream {} is approximated from per minute values",
+                            function.unwrap_or("MEAN"),
+                            condition.id,
+                            stream.title
+                        );
+                        match function {
+                            Some("STDDEV") => ("extended_stats", "mean"),
+                            _ => ("avg", "mean"),
+                        }
+                    }
+                };
+                (
+                    PanelTarget::new_metric(
+                        &query,
+                        "1m",
+                        "A",
+                        parameters.field.clone()?,
+                        metric,
+                        time_field,
+                    ),
+                    Threshold {
+                        reducer,
+                        evaluator: threshold_evaluator(parameters.threshold_type.as_deref()),
+                        value: parameters.threshold.unwrap_or(0.0),
+                    },
+                )
+            }
+            graylog::AlertConditionType::FieldContentValue => {
+                let query = format!(
+                    "{} AND {}:\"{}\"",
+                    query,
+                    parameters.field.as_ref()?,
+                    escape_phrase(parameters.value.as_ref()?)
+                );
+                (
+                    PanelTarget::new_metric(
+                        &query,
+                        "1m",
+                        "A",
+                        "select field".to_string(),
+                        "count",
+                        time_field,
+                    ),
+                    Threshold {
+                        reducer: "sum",
+                        evaluator: "gt",
+                        value: 0.0,
+                    },
+                )
+            }
+            graylog::AlertConditionType::Unknown => {
+                warn!(
+                    "Not Supported alert condition {} of stream: {}",
+                    condition.id, stream.title
+                );
+                return None;
+            }
+        };
+
+        let title = condition
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("{} ({:?})", stream.title, condition.r#type));
+        let query = match AlertQuery::datasource(datasource_uid, target, window) {
+            Ok(query) => query,
+            Err(e) => {
+                warn!(
+                    "Query of alert condition {} of stream {} can't be built: {}",
+                    condition.id, stream.title, e
+                );
+                return None;
+            }
+        };
+        let mut rule = AlertRule::new(&condition.id, title, query, threshold);
+        rule.annotations.insert(
+            "summary".to_string(),
+            format!("Converted from Graylog stream \"{}\"", stream.title),
+        );
+        if let Some(grace) = parameters.grace {
+            rule.annotations
+                .insert("graylog_grace_minutes".to_string(), grace.to_string());
+        }
+        Some(rule)
+    }
+
+    /// Converts an aggregation event definition. Reasons for skipping it or approximating
+    /// its condition are added to `report`.
+    pub fn create_alert_rule_from_event_definition(
+        definition: &graylog::EventDefinition,
+        datasource_uid: &str,
+        time_field: &str,
+        report: &mut Vec<String>,
+    ) -> Option<AlertRule> {
+        let config = &definition.config;
+        let name = format!("Event definition \"{}\"", definition.title);
+        if config.r#type != "aggregation-v1" {
+            report.push(format!("{}: type {} is not supported", name, config.r#type));
+            return None;
+        }
+
+        let mut filters = vec![];
+        if !config.streams.is_empty() {
+            let streams: Vec<_> = config
+                .streams
+                .iter()
+                .map(|s| format!("streams:{}", s))
+                .collect();
+            filters.push(format!("({})", streams.join(" OR ")));
+        }
+        let query = config.query.trim();
+        if !query.is_empty() && query != "*" {
+            filters.push(format!("({})", query));
+        }
+        let query = filters.join(" AND ");
+
+        let expression = config
+            .conditions
+            .as_ref()
+            .and_then(|c| c.expression.as_ref());
+        let (target, threshold) = match expression {
+            // Filter definitions create an event for every matching message.
+            None => (
+                PanelTarget::new_metric(
+                    &query,
+                    "1m",
+                    "A",
+                    "select field".to_string(),
+                    "count",
+                    time_field,
+                ),
+                Threshold {
+                    reducer: "sum",
+                    evaluator: "gt",
+                    value: 0.0,
+                },
+            ),
+            Some(expression) => {
+                let (series_id, operator, value) = match comparison(expression) {
+                    Some(comparison) => comparison,
+                    None => {
+                        report.push(format!(
+                            "{}: only a single comparison of a series with a number is supported",
+                            name
+                        ));
+                        return None;
+                    }
+                };
+                let series = match config.series.iter().find(|s| s.id == series_id) {
+                    Some(series) => series,
+                    None => {
+                        report.push(format!("{}: series {} is not defined", name, series_id));
+                        return None;
+                    }
+                };
+                let (metric, reducer) = match series.function.as_str() {
+                    "count" => ("count", "sum"),
+                    "sum" => ("sum", "sum"),
+                    "min" => ("min", "min"),
+                    "max" => ("max", "max"),
+                    "avg" | "stddev" | "card" => {
+                        report.push(format!(
+                            "{}: {} is approximated from per minute values",
+                            name, series.function
+                        ));
+                        match series.function.as_str() {
+                            "avg" => ("avg", "mean"),
+                            "stddev" => ("extended_stats", "mean"),
+                            _ => ("cardinality", "max"),
+                        }
+                    }
+                    function => {
+                        report.push(format!("{}: function {} is not supported", name, function));
+                        return None;
+                    }
+                };
+                let evaluator = match operator.as_str() {
+                    ">" => "gt",
+                    "<" => "lt",
+                    ">=" | "<=" => {
+                        report.push(format!(
+                            "{}: {} is approximated as {}",
+                            name,
+                            operator,
+                            &operator[..1]
+                        ));
+                        if operator == ">=" {
+                            "gt"
+                        } else {
+                            "lt"
+                        }
+                    }
+                    operator => {
+                        report.push(format!("{}: operator {} is not supported", name, operator));
+                        return None;
+                    }
+                };
+                let field = series
+                    .field
+                    .clone()
+                    .unwrap_or_else(|| "select field".to_string());
+                (
+                    PanelTarget::new_metric(&query, "1m", "A", field, metric, time_field),
+                    Threshold {
+                        reducer,
+                        evaluator,
+                        value,
+                    },
+                )
+            }
+        };
+
+        let window = (config.search_within_ms / 1000).max(EVALUATION_INTERVAL);
+        let mut query = match AlertQuery::datasource(datasource_uid, target, window) {
+            Ok(query) => query,
+            Err(e) => {
+                report.push(format!("{}: query can't be built: {}", name, e));
+                return None;
+            }
+        };
+        // Grouped aggregations become one alert instance per group.
+        let mut buckets: Vec<Value> = config
+            .group_by
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                json!({
+                    "type": "terms",
+                    "id": (i + 3).to_string(),
+                    "field": field,
+                    "settings": {"size": "0", "order": "desc", "orderBy": "_term", "min_doc_count": 1},
+                })
+            })
+            .collect();
+        if let Some(Value::Array(aggs)) = query.model.get_mut("bucketAggs") {
+            buckets.append(aggs);
+            *aggs = buckets;
+        }
+
+        let mut rule = AlertRule::new(&definition.id, definition.title.clone(), query, threshold);
+        let summary = if definition.description.is_empty() {
+            &definition.title
+        } else {
+            &definition.description
+        };
+        rule.annotations
+            .insert("summary".to_string(), summary.to_string());
+        rule.labels
+            .insert(EVENT_DEFINITION_LABEL.to_string(), definition.id.clone());
+        rule.labels.insert(
+            "graylog_priority".to_string(),
+            definition.priority.to_string(),
+        );
+        Some(rule)
+    }
+}
+
+impl AlertQuery {
+    fn datasource(
+        datasource_uid: &str,
+        target: PanelTarget,
+        window: i64,
+    ) -> Result<AlertQuery, serde_json::Error> {
+        let mut model = serde_json::to_value(target)?;
+        if model["metrics"][0]["type"] == "extended_stats" {
+            model["metrics"][0]["meta"] = json!({"std_deviation": true});
+        }
+        Ok(AlertQuery {
+            ref_id: "A".to_string(),
+            relative_time_range: Some(RelativeTimeRange {
+                from: window,
+                to: 0,
+            }),
+            datasource_uid: datasource_uid.to_string(),
+            model,
+        })
+    }
+
+    fn expression(ref_id: &str, model: Value) -> AlertQuery {
+        AlertQuery {
+            ref_id: ref_id.to_string(),
+            relative_time_range: None,
+            datasource_uid: "__expr__".to_string(),
+            model,
+        }
+    }
+}
+
+impl ContactPoint {
+    /// Converts a Graylog notification, reasons for skipping or changing it are added to `report`.
+    pub fn create_contact_point_from_graylog(
+        notification: &graylog::EventNotification,
+        report: &mut Vec<String>,
+    ) -> Option<ContactPoint> {
+        let config = &notification.config;
+        let name = format!("Notification \"{}\"", notification.title);
+        let (r#type, settings) = match config.r#type.as_str() {
+            "email-notification-v1" => {
+                if !config.user_recipients.is_empty() {
+                    report.push(format!(
+                        "{}: Graylog user recipients {} are dropped",
+                        name,
+                        config.user_recipients.join(", ")
+                    ));
+                }
+                if config.email_recipients.is_empty() {
+                    report.push(format!("{}: no email recipients left", name));
+                    return None;
+                }
+                let mut settings = json!({
+                    "addresses": config.email_recipients.join(";"),
+                    "singleEmail": false,
+                });
+                if let Some(subject) = &config.subject {
+                    settings["subject"] = Value::String(subject.clone());
+                }
+                ("email", settings)
+            }
+            "http-notification-v1" => match &config.url {
+                Some(url) => ("webhook", json!({ "url": url })),
+                None => {
+                    report.push(format!("{}: webhook has no url", name));
+                    return None;
+                }
+            },
+            "slack-notification-v1" => {
+                let url = match &config.webhook_url {
+                    Some(url) => url,
+                    None => {
+                        report.push(format!("{}: Slack notification has no webhook url", name));
+                        return None;
+                    }
+                };
+                let mut settings = json!({ "url": url });
+                if let Some(channel) = &config.channel {
+                    settings["recipient"] = Value::String(channel.clone());
+                }
+                ("slack", settings)
+            }
+            other => {
+                report.push(format!("{}: type {} is not supported", name, other));
+                return None;
+            }
+        };
+        Some(ContactPoint {
+            name: notification.title.clone(),
+            receivers: vec![Receiver {
+                uid: notification.id.chars().take(40).collect(),
+                r#type: r#type.to_string(),
+                settings,
+            }],
+        })
+    }
+}
+
+impl NotificationPolicy {
+    pub fn new(receiver: &str) -> NotificationPolicy {
+        NotificationPolicy {
+            receiver: receiver.to_string(),
+            routes: vec![],
+            other: BTreeMap::new(),
+        }
+    }
+
+    /// Replaces all routes for converted event definitions by `routes`, other routes are kept.
+    pub fn merge_routes(&mut self, routes: Vec<NotificationRoute>) {
+        self.routes
+            .retain(|route| !route.is_event_definition_route());
+        self.routes.extend(routes);
+    }
+}
+
+impl NotificationRoute {
+    fn is_event_definition_route(&self) -> bool {
+        self.object_matchers
+            .iter()
+            .any(|m| m.first().map(String::as_str) == Some(EVENT_DEFINITION_LABEL))
+    }
+}
+
+impl EventsConversion {
+    pub fn create_from_graylog(
+        definitions: &[graylog::EventDefinition],
+        notifications: &[graylog::EventNotification],
+        datasource_uid: &str,
+        time_field: &str,
+    ) -> EventsConversion {
+        let mut report = vec![];
+        let contact_points: BTreeMap<_, _> = notifications
+            .iter()
+            .filter_map(|n| {
+                ContactPoint::create_contact_point_from_graylog(n, &mut report)
+                    .map(|c| (n.id.clone(), c))
+            })
+            .collect();
+
+        let mut groups: BTreeMap<i64, Vec<AlertRule>> = BTreeMap::new();
+        let mut routes = vec![];
+        for definition in definitions {
+            let rule = match AlertRule::create_alert_rule_from_event_definition(
+                definition,
+                datasource_uid,
+                time_field,
+                &mut report,
+            ) {
+                Some(rule) => rule,
+                None => continue,
+            };
+            let interval = (definition.config.execute_every_ms / 1000).max(10);
+            groups.entry(interval).or_default().push(rule);
+
+            let grace = definition.notification_settings.grace_period_ms / 1000;
+            for handler in &definition.notifications {
+                match contact_points.get(&handler.notification_id) {
+                    Some(contact_point) => routes.push(NotificationRoute {
+                        receiver: Some(contact_point.name.clone()),
+                        object_matchers: vec![vec![
+                            EVENT_DEFINITION_LABEL.to_string(),
+                            "=".to_string(),
+                            definition.id.clone(),
+                        ]],
+                        continue_matching: true,
+                        repeat_interval: if grace > 0 {
+                            Some(format!("{}s", grace))
+                        } else {
+                            None
+                        },
+                        other: BTreeMap::new(),
+                    }),
+                    None => report.push(format!(
+                        "Event definition \"{}\": notification {} is not available",
+                        definition.title, handler.notification_id
+                    )),
+                }
+            }
+        }
+
+        EventsConversion {
+            groups: groups
+                .into_iter()
+                .map(|(interval, rules)| RuleGroup {
+                    title: format!("Graylog events every {}s", interval),
+                    interval,
+                    rules,
+                })
+                .collect(),
+            contact_points: contact_points.into_values().collect(),
+            routes,
+            report,
+        }
+    }
+}
+
+/// Extracts `(series id, operator, number)` from a comparison of a series with a number.
+fn comparison(expression: &Value) -> Option<(String, String, f64)> {
+    let operator = expression["expr"].as_str()?;
+    let flipped = match operator {
+        ">" => "<",
+        "<" => ">",
+        ">=" => "<=",
+        "<=" => ">=",
+        "==" => "==",
+        _ => return None,
+    };
+    let (left, right) = (&expression["left"], &expression["right"]);
+    match (left["expr"].as_str()?, right["expr"].as_str()?) {
+        ("number-ref", "number") => Some((
+            left["ref"].as_str()?.to_string(),
+            operator.to_string(),
+            right["value"].as_f64()?,
+        )),
+        ("number", "number-ref") => Some((
+            right["ref"].as_str()?.to_string(),
+            flipped.to_string(),
+            left["value"].as_f64()?,
+        )),
+        _ => None,
+    }
+}
+
+/// Escapes `value` for a quoted Lucene phrase.
+fn escape_phrase(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn threshold_evaluator(threshold_type: Option<&str>) -> &'static str {
+    match threshold_type {
+        Some("LESS") | Some("LOWER") => "lt",
+        _ => "gt",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(conditions: Value) -> graylog::Stream {
+        serde_json::from_value(json!({
+            "id": "5d0a",
+            "title": "Errors",
+            "alert_conditions": conditions,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn message_count_condition_counts_stream_messages() {
+        let stream = stream(json!([{
+            "id": "c1",
+            "type": "message_count",
+            "title": "Too many errors",
+            "parameters": {"time": 5, "threshold": 10, "threshold_type": "MORE", "query": "level:3"},
+        }]));
+
+        let rule =
+            AlertRule::create_alert_rule(&stream, &stream.alert_conditions[0], "ds", "timestamp")
+                .unwrap();
+
+        assert_eq!("Too many errors", rule.title);
+        assert_eq!(300, rule.data[0].relative_time_range.as_ref().unwrap().from);
+        assert_eq!(
+            json!("streams:5d0a AND (level:3)"),
+            rule.data[0].model["query"]
+        );
+        assert_eq!(json!("count"), rule.data[0].model["metrics"][0]["type"]);
+        assert_eq!(
+            json!({"type": "gt", "params": [10.0]}),
+            rule.data[2].model["conditions"][0]["evaluator"]
+        );
+        assert_eq!("OK", rule.no_data_state);
+    }
+
+    #[test]
+    fn field_value_condition_uses_field_aggregation() {
+        let stream = stream(json!([{
+            "id": "c2",
+            "type": "field_value",
+            "parameters": {"time": 1, "threshold": 2.5, "threshold_type": "LOWER", "type": "MIN", "field": "took_ms"},
+        }]));
+
+        let rule =
+            AlertRule::create_alert_rule(&stream, &stream.alert_conditions[0], "ds", "@timestamp")
+                .unwrap();
+
+        assert_eq!(json!("streams:5d0a"), rule.data[0].model["query"]);
+        assert_eq!(json!("@timestamp"), rule.data[0].model["timeField"]);
+        assert_eq!(
+            json!("@timestamp"),
+            rule.data[0].model["bucketAggs"][0]["field"]
+        );
+        assert_eq!(json!("min"), rule.data[0].model["metrics"][0]["type"]);
+        assert_eq!(json!("took_ms"), rule.data[0].model["metrics"][0]["field"]);
+        assert_eq!(json!("min"), rule.data[1].model["reducer"]);
+        assert_eq!(
+            json!("lt"),
+            rule.data[2].model["conditions"][0]["evaluator"]["type"]
+        );
+        assert_eq!("Alerting", rule.no_data_state);
+    }
+
+    #[test]
+    fn field_content_value_condition_escapes_value() {
+        let stream = stream(json!([{
+            "id": "c4",
+            "type": "field_content_value",
+            "parameters": {"field": "path", "value": "C:\\temp \"old\""},
+        }]));
+
+        let rule =
+            AlertRule::create_alert_rule(&stream, &stream.alert_conditions[0], "ds", "timestamp")
+                .unwrap();
+
+        assert_eq!(
+            json!("streams:5d0a AND path:\"C:\\\\temp \\\"old\\\"\""),
+            rule.data[0].model["query"]
+        );
+    }
+
+    #[test]
+    fn unknown_conditions_are_skipped() {
+        let stream = stream(json!([{"id": "c3", "type": "plugin_condition", "parameters": {}}]));
+
+        assert_eq!(
+            graylog::AlertConditionType::Unknown,
+            stream.alert_conditions[0].r#type
+        );
+        assert!(AlertRule::create_alert_rule(
+            &stream,
+            &stream.alert_conditions[0],
+            "ds",
+            "timestamp"
+        )
+        .is_none());
+    }
+
+    fn definition(config: Value) -> graylog::EventDefinition {
+        serde_json::from_value(json!({
+            "id": "def1",
+            "title": "Slow requests",
+            "priority": 2,
+            "config": config,
+            "notifications": [{"notification_id": "n1"}, {"notification_id": "gone"}],
+            "notification_settings": {"grace_period_ms": 300000},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn aggregation_event_definition_with_group_by() {
+        let definition = definition(json!({
+            "type": "aggregation-v1",
+            "query": "level:3",
+            "streams": ["s1", "s2"],
+            "group_by": ["source"],
+            "series": [{"id": "max-took", "function": "max", "field": "took_ms"}],
+            "conditions": {"expression": {
+                "expr": "<",
+                "left": {"expr": "number", "value": 500},
+                "right": {"expr": "number-ref", "ref": "max-took"},
+            }},
+            "search_within_ms": 300000,
+            "execute_every_ms": 60000,
+        }));
+        let mut report = vec![];
+
+        let rule = AlertRule::create_alert_rule_from_event_definition(
+            &definition,
+            "ds",
+            "timestamp",
+            &mut report,
+        )
+        .unwrap();
+
+        assert!(report.is_empty());
+        let model = &rule.data[0].model;
+        assert_eq!(
+            json!("(streams:s1 OR streams:s2) AND (level:3)"),
+            model["query"]
+        );
+        assert_eq!(json!("max"), model["metrics"][0]["type"]);
+        assert_eq!(json!("terms"), model["bucketAggs"][0]["type"]);
+        assert_eq!(json!("date_histogram"), model["bucketAggs"][1]["type"]);
+        assert_eq!(
+            json!({"type": "gt", "params": [500.0]}),
+            rule.data[2].model["conditions"][0]["evaluator"]
+        );
+        assert_eq!("def1", rule.labels[EVENT_DEFINITION_LABEL]);
+    }
+
+    #[test]
+    fn unsupported_expressions_are_reported() {
+        let definition = definition(json!({
+            "type": "aggregation-v1",
+            "series": [{"id": "a", "function": "count"}, {"id": "b", "function": "count"}],
+            "conditions": {"expression": {
+                "expr": "&&",
+                "left": {"expr": ">", "left": {"expr": "number-ref", "ref": "a"}, "right": {"expr": "number", "value": 1}},
+                "right": {"expr": ">", "left": {"expr": "number-ref", "ref": "b"}, "right": {"expr": "number", "value": 1}},
+            }},
+        }));
+        let mut report = vec![];
+
+        assert!(AlertRule::create_alert_rule_from_event_definition(
+            &definition,
+            "ds",
+            "timestamp",
+            &mut report
+        )
+        .is_none());
+        assert_eq!(1, report.len());
+    }
+
+    #[test]
+    fn webhook_without_url_is_reported() {
+        let notification: graylog::EventNotification = serde_json::from_value(json!({
+            "id": "n1",
+            "title": "Hook",
+            "config": {"type": "http-notification-v1"},
+        }))
+        .unwrap();
+        let mut report = vec![];
+
+        assert!(
+            ContactPoint::create_contact_point_from_graylog(&notification, &mut report).is_none()
+        );
+        assert_eq!(vec!["Notification \"Hook\": webhook has no url"], report);
+    }
+
+    #[test]
+    fn events_conversion_routes_notifications() {
+        let definition = definition(json!({"type": "aggregation-v1", "query": "level:1"}));
+        let notifications: Vec<graylog::EventNotification> = serde_json::from_value(json!([{
+            "id": "n1",
+            "title": "Ops mail",
+            "config": {
+                "type": "email-notification-v1",
+                "email_recipients": ["ops@example.com"],
+                "user_recipients": ["admin"],
+            },
+        }]))
+        .unwrap();
+
+        let conversion =
+            EventsConversion::create_from_graylog(&[definition], &notifications, "ds", "timestamp");
+
+        assert_eq!(1, conversion.groups.len());
+        assert_eq!(1, conversion.contact_points.len());
+        assert_eq!(
+            json!({"addresses": "ops@example.com", "singleEmail": false}),
+            conversion.contact_points[0].receivers[0].settings
+        );
+        assert_eq!(1, conversion.routes.len());
+        assert_eq!(
+            Some("300s".to_string()),
+            conversion.routes[0].repeat_interval
+        );
+        // dropped user recipient and the missing notification
+        assert_eq!(2, conversion.report.len());
+
+        let mut policy: NotificationPolicy = serde_json::from_value(json!({
+            "receiver": "default",
+            "group_by": ["alertname"],
+            "routes": [
+                {"receiver": "team", "object_matchers": [["team", "=", "a"]]},
+                {"receiver": "old", "object_matchers": [[EVENT_DEFINITION_LABEL, "=", "x"]]},
+            ],
+        }))
+        .unwrap();
+        policy.merge_routes(conversion.routes);
+        let policy = serde_json::to_value(policy).unwrap();
+        assert_eq!(json!(["alertname"]), policy["group_by"]);
+        assert_eq!(json!("team"), policy["routes"][0]["receiver"]);
+        assert_eq!(json!("Ops mail"), policy["routes"][1]["receiver"]);
+        assert_eq!(json!(null), policy["routes"][2]);
+    }
+
+    #[test]
+    fn provisioning_writes_policies_only_when_given() {
+        let provisioning = Provisioning::new(vec![], "Graylog");
+        let value = serde_json::to_value(&provisioning).unwrap();
+        assert_eq!(json!(null), value["policies"]);
+
+        let provisioning = provisioning.with_policy(NotificationPolicy::new("default"));
+        let value = serde_json::to_value(&provisioning).unwrap();
+        assert_eq!(
+            json!([{"orgId": 1, "receiver": "default"}]),
+            value["policies"]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::client::GrafanaClient;
+use log::info;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Timestamped directory receiving dashboards before they get overwritten.
+pub struct Backup {
+    dir: PathBuf,
+}
+
+impl Backup {
+    pub fn new(root: &Path) -> Backup {
+        let mut dir = root.to_path_buf();
+        dir.push(chrono::Local::now().format("%Y-%m-%dT%H-%M-%S").to_string());
+        Backup { dir }
+    }
+
+    /// Saves a dashboard as returned by `/api/dashboards/uid/...`, including its `meta` block.
+    pub fn save(&self, stored: &Value) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let uid = stored["dashboard"]["uid"].as_str().unwrap_or("unknown");
+        let mut path = self.dir.clone();
+        path.push(format!("{}.json", uid));
+        serde_json::to_writer_pretty(File::create(&path)?, stored)?;
+        info!("Backed up dashboard {} to {}", uid, path.display());
+        Ok(path)
+    }
+}
+
+/// Uploads a single backup file or every backup file in a directory.
+pub fn restore(
+    client: &GrafanaClient,
+    path: &Path,
+    folder: Option<i64>,
+) -> Result<(), Box<dyn Error>> {
+    let mut files = vec![];
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let file = entry?.path();
+            if file.extension().and_then(|e| e.to_str()) == Some("json") {
+                files.push(file);
+            }
+        }
+        files.sort();
+    } else {
+        files.push(path.to_path_buf());
+    }
+
+    for file in files {
+        let stored: Value = serde_json::from_reader(BufReader::new(File::open(&file)?))?;
+        client.post_dashboard(&restore_request(stored, folder))?;
+        info!("Restored dashboard from {}", file.display());
+    }
+    Ok(())
+}
+
+fn restore_request(mut stored: Value, folder: Option<i64>) -> Value {
+    let folder_id = folder.unwrap_or_else(|| stored["meta"]["folderId"].as_i64().unwrap_or(0));
+    let mut dashboard = stored["dashboard"].take();
+    // The numeric id is local to the Grafana instance the backup was taken from.
+    dashboard["id"] = Value::Null;
+    json!({
+        "dashboard": dashboard,
+        "folderId": folder_id,
+        "overwrite": true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_request_keeps_original_folder_and_drops_id() {
+        let stored = json!({
+            "dashboard": {"id": 12, "uid": "abc", "title": "Abc"},
+            "meta": {"folderId": 3},
+        });
+
+        let request = restore_request(stored, None);
+
+        assert_eq!(json!(3), request["folderId"]);
+        assert_eq!(Value::Null, request["dashboard"]["id"]);
+        assert_eq!(json!("abc"), request["dashboard"]["uid"]);
+    }
+
+    #[test]
+    fn restore_request_with_folder_override() {
+        let stored = json!({"dashboard": {"uid": "abc"}, "meta": {"folderId": 3}});
+
+        assert_eq!(json!(7), restore_request(stored, Some(7))["folderId"]);
+    }
+}
@@ -0,0 +1,593 @@
+use crate::credentials::{Credentials, Secret};
+use crate::{alerting, dashboard_v2, export, grafana, graylog};
+use log::warn;
+use reqwest::header::{HeaderValue, RETRY_AFTER};
+use reqwest::{Certificate, Client, Identity, Proxy, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+/// TLS, proxy and timeout settings shared by the Grafana and Graylog clients.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionOptions {
+    /// PEM file with CA certificates trusted in addition to the system ones.
+    pub ca_cert: Option<PathBuf>,
+    /// PKCS#12 file with client certificate and key for mutual TLS.
+    pub client_cert: Option<PathBuf>,
+    pub client_cert_password: Option<Secret>,
+    /// Accept invalid certificates and host names, only meant for test setups.
+    #[serde(default)]
+    pub insecure: bool,
+    /// Proxy for all requests, e.g. `http://proxy:3128`.
+    pub proxy: Option<String>,
+    /// Seconds a whole request may take.
+    pub timeout: Option<u64>,
+    /// Seconds connecting may take.
+    pub connect_timeout: Option<u64>,
+    /// How often failed requests are retried, defaults to 3.
+    pub retries: Option<u32>,
+}
+
+impl ConnectionOptions {
+    pub fn build_client(&self) -> Result<Client, Box<dyn Error>> {
+        let mut builder = Client::builder();
+        if let Some(path) = &self.ca_cert {
+            let bundle = fs::read(path)
+                .map_err(|e| format!("Can't read CA bundle {}: {}", path.display(), e))?;
+            for pem in split_pem_bundle(&bundle) {
+                builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+            }
+        }
+        if let Some(path) = &self.client_cert {
+            let der = fs::read(path)
+                .map_err(|e| format!("Can't read client certificate {}: {}", path.display(), e))?;
+            let password = self
+                .client_cert_password
+                .as_ref()
+                .map(Secret::expose)
+                .unwrap_or("");
+            builder = builder.identity(Identity::from_pkcs12_der(&der, password)?);
+        }
+        if self.insecure {
+            builder = builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(Duration::from_secs(timeout));
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Delay before the first retry, doubled for every further one.
+const BASE_DELAY: Duration = Duration::from_millis(500);
+/// Longest wait before a retry, also limiting the `Retry-After` of a server.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Sends a request, retrying it with exponential backoff when the server is rate limiting.
+/// Requests which are `idempotent` are also retried after connection errors, gateway errors
+/// and while the server is unavailable, as these may have been processed already.
+fn send_with_retry(
+    client: &Client,
+    request: RequestBuilder,
+    retries: u32,
+    idempotent: bool,
+) -> Result<Response, Box<dyn Error>> {
+    let request = request.build()?;
+    let mut attempt = 0;
+    loop {
+        let retry = match request.try_clone() {
+            Some(retry) if attempt < retries => retry,
+            _ => return Ok(client.execute(request)?),
+        };
+        let result = client.execute(retry);
+        let delay = match &result {
+            Ok(response) => retry_delay(
+                response.status(),
+                response.headers().get(RETRY_AFTER),
+                attempt,
+                idempotent,
+            ),
+            Err(_) if idempotent => Some(backoff(attempt)),
+            Err(_) => None,
+        };
+        match delay {
+            Some(delay) => {
+                warn!(
+                    "{} {} failed ({}), retrying in {:?}",
+                    request.method(),
+                    request.url(),
+                    match &result {
+                        Ok(response) => response.status().to_string(),
+                        Err(e) => e.to_string(),
+                    },
+                    delay
+                );
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            None => return Ok(result?),
+        }
+    }
+}
+
+/// Delay before retrying a request which got `status`, `None` if it shouldn't be retried.
+fn retry_delay(
+    status: StatusCode,
+    retry_after: Option<&HeaderValue>,
+    attempt: u32,
+    idempotent: bool,
+) -> Option<Duration> {
+    let requested = || {
+        retry_after
+            .and_then(parse_retry_after)
+            .map(|delay| delay.min(MAX_DELAY))
+            .unwrap_or_else(|| backoff(attempt))
+    };
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => Some(requested()),
+        StatusCode::SERVICE_UNAVAILABLE if idempotent => Some(requested()),
+        StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT if idempotent => {
+            Some(backoff(attempt))
+        }
+        _ => None,
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY
+        .checked_mul(2u32.saturating_pow(attempt))
+        .map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY))
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+/// Splits a PEM file into its certificates, reqwest only reads the first one of a bundle.
+fn split_pem_bundle(bundle: &[u8]) -> Vec<Vec<u8>> {
+    const END: &str = "-----END CERTIFICATE-----";
+    String::from_utf8_lossy(bundle)
+        .split_inclusive(END)
+        .filter(|pem| pem.contains(END))
+        .map(|pem| pem.trim().as_bytes().to_vec())
+        .collect()
+}
+
+pub struct GrafanaClient {
+    url: String,
+    credentials: Credentials,
+    client: Client,
+    retries: u32,
+}
+
+impl GrafanaClient {
+    pub fn new(
+        url: &str,
+        credentials: Credentials,
+        connection: &ConnectionOptions,
+    ) -> Result<GrafanaClient, Box<dyn Error>> {
+        Ok(GrafanaClient {
+            url: url.trim_end_matches('/').to_string(),
+            credentials,
+            client: connection.build_client()?,
+            retries: connection.retries.unwrap_or(DEFAULT_RETRIES),
+        })
+    }
+
+    /// Tokens of service accounts and API keys are bearer tokens.
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.credentials {
+            Credentials::None => request,
+            Credentials::Token(token) => {
+                request.header("Authorization", format!("Bearer {}", token.expose()))
+            }
+            Credentials::Basic { user, password } => {
+                request.basic_auth(user, Some(password.expose()))
+            }
+        }
+    }
+
+    fn send(&self, request: RequestBuilder, idempotent: bool) -> Result<Response, Box<dyn Error>> {
+        send_with_retry(
+            &self.client,
+            self.authorize(request),
+            self.retries,
+            idempotent,
+        )
+    }
+
+    /// Returns the dashboard stored under `uid` together with its `meta` block,
+    /// or `None` if Grafana doesn't know it.
+    pub fn get_dashboard(&self, uid: &str) -> Result<Option<Value>, Box<dyn Error>> {
+        let url = format!("{}/api/dashboards/uid/{}", self.url, uid);
+        let response = self.send(self.client.get(&url), true)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json()?))
+    }
+
+    /// Looks up the dashboard a converted dashboard would replace, first by uid and
+    /// then by title within `folder_id`.
+    pub fn find_dashboard(
+        &self,
+        dashboard: &grafana::Dashboard,
+        folder_id: i64,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        if let Some(existing) = self.get_dashboard(&dashboard.uid)? {
+            return Ok(Some(existing));
+        }
+        let hits = self.search(&[("query", dashboard.title.as_str()), ("type", "dash-db")])?;
+        let uid = hits
+            .iter()
+            .find(|hit| {
+                hit["title"] == dashboard.title.as_str()
+                    && hit["folderId"].as_i64().unwrap_or(0) == folder_id
+            })
+            .and_then(|hit| hit["uid"].as_str());
+        match uid {
+            Some(uid) => self.get_dashboard(uid),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the search hits for all dashboards in `folder_id` carrying every one of `tags`.
+    pub fn search_tagged(
+        &self,
+        tags: &[String],
+        folder_id: i64,
+    ) -> Result<Vec<Value>, Box<dyn Error>> {
+        let folder = folder_id.to_string();
+        let mut query = vec![("type", "dash-db"), ("folderIds", folder.as_str())];
+        query.extend(tags.iter().map(|tag| ("tag", tag.as_str())));
+        Ok(self
+            .search(&query)?
+            .into_iter()
+            .filter(|hit| hit["folderId"].as_i64().unwrap_or(0) == folder_id)
+            .collect())
+    }
+
+    fn search(&self, query: &[(&str, &str)]) -> Result<Vec<Value>, Box<dyn Error>> {
+        let url = format!("{}/api/search", self.url);
+        let hits = self
+            .send(self.client.get(&url).query(query), true)?
+            .error_for_status()?
+            .json()?;
+        Ok(hits)
+    }
+
+    /// Creates or updates a dashboard, returning Grafana's answer with the new `uid` and `version`.
+    pub fn post_dashboard<T: Serialize>(&self, dashboard: &T) -> Result<Value, Box<dyn Error>> {
+        let url = format!("{}{}", self.url, "/api/dashboards/db/");
+        let response = self
+            .send(self.client.post(&url).json(dashboard), true)?
+            .error_for_status()?
+            .json()?;
+        Ok(response)
+    }
+
+    /// Imports a dashboard exported for sharing, filling its inputs.
+    pub fn import_dashboard(
+        &self,
+        request: &export::ImportRequest,
+    ) -> Result<Value, Box<dyn Error>> {
+        let url = format!("{}/api/dashboards/import", self.url);
+        let response = self
+            .send(self.client.post(&url).json(request), true)?
+            .error_for_status()?
+            .json()?;
+        Ok(response)
+    }
+
+    /// Version of Grafana as reported by `/api/health`, which needs no authentication.
+    pub fn get_version(&self) -> Result<String, Box<dyn Error>> {
+        let url = format!("{}/api/health", self.url);
+        let health: Value = self
+            .send(self.client.get(&url), true)?
+            .error_for_status()?
+            .json()?;
+        match health["version"].as_str() {
+            Some(version) => Ok(version.to_string()),
+            None => Err("Grafana reported no version".into()),
+        }
+    }
+
+    /// Whether Grafana serves the resource API `group_version`, like `dashboard.grafana.app/v2alpha1`,
+    /// which can be disabled by feature toggles regardless of the version.
+    pub fn serves_api(&self, group_version: &str) -> Result<bool, Box<dyn Error>> {
+        let url = format!("{}/apis/{}", self.url, group_version);
+        let response = self.send(self.client.get(&url), true)?;
+        if response.status().is_client_error() {
+            return Ok(false);
+        }
+        response.error_for_status()?;
+        Ok(true)
+    }
+
+    /// Resolves the uid of a folder, which v2 dashboards use to reference it.
+    pub fn get_folder_uid(&self, id: i64) -> Result<String, Box<dyn Error>> {
+        let url = format!("{}/api/folders/id/{}", self.url, id);
+        let folder: Value = self
+            .send(self.client.get(&url), true)?
+            .error_for_status()?
+            .json()?;
+        match folder["uid"].as_str() {
+            Some(uid) => Ok(uid.to_string()),
+            None => Err(format!("Folder {} has no uid", id).into()),
+        }
+    }
+
+    /// Creates or replaces a dashboard resource of the v2 schema in `namespace`.
+    pub fn put_dashboard_v2(
+        &self,
+        namespace: &str,
+        resource: &Value,
+    ) -> Result<(), Box<dyn Error>> {
+        let url = format!(
+            "{}/apis/{}/namespaces/{}/dashboards",
+            self.url,
+            dashboard_v2::API_VERSION,
+            utf8_percent_encode(namespace, PATH_SEGMENT_ENCODE_SET)
+        );
+        let name = resource["metadata"]["name"].as_str().unwrap_or_default();
+        let item_url = format!(
+            "{}/{}",
+            url,
+            utf8_percent_encode(name, PATH_SEGMENT_ENCODE_SET)
+        );
+        let response = self.send(self.client.get(&item_url), true)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            self.send(self.client.post(&url).json(resource), false)?
+                .error_for_status()?;
+            return Ok(());
+        }
+        let existing: Value = response.error_for_status()?.json()?;
+        let mut resource = resource.clone();
+        resource["metadata"]["resourceVersion"] = existing["metadata"]["resourceVersion"].clone();
+        self.send(self.client.put(&item_url).json(&resource), true)?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Resolves the uid of a datasource, which alert rules use to reference it.
+    pub fn get_datasource_uid(&self, name: &str) -> Result<String, Box<dyn Error>> {
+        let url = format!(
+            "{}/api/datasources/name/{}",
+            self.url,
+            utf8_percent_encode(name, PATH_SEGMENT_ENCODE_SET)
+        );
+        let datasource: Value = self
+            .send(self.client.get(&url), true)?
+            .error_for_status()?
+            .json()?;
+        match datasource["uid"].as_str() {
+            Some(uid) => Ok(uid.to_string()),
+            None => Err(format!("Datasource {} has no uid", name).into()),
+        }
+    }
+
+    /// Creates or replaces an alert rule group. Rules stay editable in the Grafana UI.
+    pub fn put_rule_group(&self, group: &alerting::ApiRuleGroup) -> Result<(), Box<dyn Error>> {
+        let url = format!(
+            "{}/api/v1/provisioning/folder/{}/rule-groups/{}",
+            self.url,
+            utf8_percent_encode(&group.folder_uid, PATH_SEGMENT_ENCODE_SET),
+            utf8_percent_encode(&group.title, PATH_SEGMENT_ENCODE_SET)
+        );
+        self.send(
+            self.client
+                .put(&url)
+                .header("X-Disable-Provenance", "true")
+                .json(group),
+            true,
+        )?
+        .error_for_status()?;
+        Ok(())
+    }
+
+    /// Creates a contact point or updates the one with the same receiver uid.
+    pub fn put_contact_point(
+        &self,
+        contact_point: &alerting::ContactPoint,
+    ) -> Result<(), Box<dyn Error>> {
+        let url = format!("{}/api/v1/provisioning/contact-points", self.url);
+        let existing: Vec<Value> = self
+            .send(self.client.get(&url), true)?
+            .error_for_status()?
+            .json()?;
+        for receiver in &contact_point.receivers {
+            let body = json!({
+                "uid": receiver.uid,
+                "name": contact_point.name,
+                "type": receiver.r#type,
+                "settings": receiver.settings,
+            });
+            let exists = existing.iter().any(|c| c["uid"] == receiver.uid.as_str());
+            let request = if exists {
+                self.client.put(&format!(
+                    "{}/{}",
+                    url,
+                    utf8_percent_encode(&receiver.uid, PATH_SEGMENT_ENCODE_SET)
+                ))
+            } else {
+                self.client.post(&url)
+            };
+            self.send(
+                request.header("X-Disable-Provenance", "true").json(&body),
+                exists,
+            )?
+            .error_for_status()?;
+        }
+        Ok(())
+    }
+
+    pub fn get_notification_policy(&self) -> Result<alerting::NotificationPolicy, Box<dyn Error>> {
+        let url = format!("{}/api/v1/provisioning/policies", self.url);
+        let policy = self
+            .send(self.client.get(&url), true)?
+            .error_for_status()?
+            .json()?;
+        Ok(policy)
+    }
+
+    /// Replaces the whole notification policy tree.
+    pub fn put_notification_policy(
+        &self,
+        policy: &alerting::NotificationPolicy,
+    ) -> Result<(), Box<dyn Error>> {
+        let url = format!("{}/api/v1/provisioning/policies", self.url);
+        self.send(
+            self.client
+                .put(&url)
+                .header("X-Disable-Provenance", "true")
+                .json(policy),
+            true,
+        )?
+        .error_for_status()?;
+        Ok(())
+    }
+
+    /// Deletes the dashboard stored under `uid`, a dashboard which is already gone is no error.
+    pub fn delete_dashboard(&self, uid: &str) -> Result<(), Box<dyn Error>> {
+        let url = format!("{}/api/dashboards/uid/{}", self.url, uid);
+        let response = self.send(self.client.delete(&url), true)?;
+        if response.status() != StatusCode::NOT_FOUND {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+pub struct GraylogClient {
+    url: String,
+    credentials: Credentials,
+    client: Client,
+    retries: u32,
+}
+
+impl GraylogClient {
+    pub fn new(
+        url: &str,
+        credentials: Credentials,
+        connection: &ConnectionOptions,
+    ) -> Result<GraylogClient, Box<dyn Error>> {
+        Ok(GraylogClient {
+            url: url.trim_end_matches('/').to_string(),
+            credentials,
+            client: connection.build_client()?,
+            retries: connection.retries.unwrap_or(DEFAULT_RETRIES),
+        })
+    }
+
+    /// Fetches all dashboards visible to the access token, wrapped into a content pack named `name`.
+    pub fn get_content_pack(&self, name: &str) -> Result<graylog::ContentPack, Box<dyn Error>> {
+        let dashboards: graylog::ApiDashboards = self.get("/api/dashboards")?;
+        Ok(graylog::ContentPack {
+            name: name.to_string(),
+            dashboards: dashboards
+                .dashboards
+                .into_iter()
+                .map(graylog::Dashboard::from)
+                .collect(),
+            streams: vec![],
+            entities: vec![],
+        })
+    }
+
+    pub fn get_event_definitions(&self) -> Result<Vec<graylog::EventDefinition>, Box<dyn Error>> {
+        let definitions: graylog::ApiEventDefinitions = self.get("/api/events/definitions")?;
+        Ok(definitions.event_definitions)
+    }
+
+    pub fn get_notifications(&self) -> Result<Vec<graylog::EventNotification>, Box<dyn Error>> {
+        let notifications: graylog::ApiEventNotifications =
+            self.get("/api/events/notifications")?;
+        Ok(notifications.notifications)
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, Box<dyn Error>> {
+        let url = format!("{}{}", self.url, path);
+        let request = self.client.get(&url).header("Accept", "application/json");
+        let request = match &self.credentials {
+            Credentials::None => request,
+            // Graylog expects access tokens as user name with the literal password "token".
+            Credentials::Token(token) => request.basic_auth(token.expose(), Some("token")),
+            Credentials::Basic { user, password } => {
+                request.basic_auth(user, Some(password.expose()))
+            }
+        };
+        Ok(send_with_retry(&self.client, request, self.retries, true)?
+            .error_for_status()?
+            .json()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_pem_bundle_into_certificates() {
+        let bundle =
+            b"# internal CA\n-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n\
+                       -----BEGIN CERTIFICATE-----\nMIIC\n-----END CERTIFICATE-----\n";
+
+        let pems = split_pem_bundle(bundle);
+
+        assert_eq!(2, pems.len());
+        assert!(pems[1].starts_with(b"-----BEGIN CERTIFICATE-----\nMIIC"));
+        assert!(pems[0].ends_with(b"-----END CERTIFICATE-----"));
+    }
+
+    #[test]
+    fn retry_delay_honours_retry_after_and_backs_off() {
+        let retry_after = HeaderValue::from_static("7");
+        assert_eq!(
+            Some(Duration::from_secs(7)),
+            retry_delay(StatusCode::TOO_MANY_REQUESTS, Some(&retry_after), 0, false)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(2)),
+            retry_delay(StatusCode::BAD_GATEWAY, None, 2, true)
+        );
+        assert_eq!(None, retry_delay(StatusCode::BAD_GATEWAY, None, 0, false));
+        assert_eq!(
+            Some(Duration::from_secs(7)),
+            retry_delay(StatusCode::SERVICE_UNAVAILABLE, Some(&retry_after), 0, true)
+        );
+        assert_eq!(
+            None,
+            retry_delay(
+                StatusCode::SERVICE_UNAVAILABLE,
+                Some(&retry_after),
+                0,
+                false
+            )
+        );
+        assert_eq!(None, retry_delay(StatusCode::NOT_FOUND, None, 0, true));
+        assert_eq!(MAX_DELAY, backoff(30));
+    }
+}
@@ -0,0 +1,302 @@
+use crate::client::ConnectionOptions;
+use crate::credentials::{CredentialSources, Secret};
+use crate::grafana::TimeRange;
+use crate::ir;
+use crate::policy::FailurePolicy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings read from a TOML or YAML file, options given on the command line win.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub grafana: GrafanaConfig,
+    #[serde(default)]
+    pub graylog: GraylogConfig,
+    /// TLS, proxy and timeouts for connections to Grafana and Graylog.
+    #[serde(default)]
+    pub connection: ConnectionOptions,
+    /// Elasticsearch field holding the message time.
+    pub time_field: Option<String>,
+    /// Message fields shown by the "Go to Graylog" links.
+    pub link_fields: Option<String>,
+    /// Settings for all dashboards.
+    #[serde(default)]
+    pub defaults: DashboardSettings,
+    /// Settings for single dashboards, applied in order on top of `defaults`.
+    #[serde(default)]
+    pub dashboards: Vec<DashboardOverride>,
+    /// What skipped widgets, approximated metrics and other conversion issues lead to.
+    #[serde(default)]
+    pub policy: FailurePolicy,
+}
+
+/// Secrets are better given by `token_file`/`password_file` than inline.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GrafanaConfig {
+    pub url: Option<String>,
+    pub token: Option<Secret>,
+    pub token_file: Option<PathBuf>,
+    pub user: Option<String>,
+    pub password: Option<Secret>,
+    pub password_file: Option<PathBuf>,
+}
+
+impl GrafanaConfig {
+    pub fn credentials(&self) -> CredentialSources {
+        CredentialSources {
+            token: self.token.clone(),
+            token_file: self.token_file.clone(),
+            user: self.user.clone(),
+            password: self.password.clone(),
+            password_file: self.password_file.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GraylogConfig {
+    pub url: Option<String>,
+    /// Name of the content pack read from the Graylog API, which converted dashboards
+    /// are tagged with.
+    pub content_pack_name: Option<String>,
+    pub token: Option<Secret>,
+    pub token_file: Option<PathBuf>,
+    pub user: Option<String>,
+    pub password: Option<Secret>,
+    pub password_file: Option<PathBuf>,
+}
+
+impl GraylogConfig {
+    pub fn credentials(&self) -> CredentialSources {
+        CredentialSources {
+            token: self.token.clone(),
+            token_file: self.token_file.clone(),
+            user: self.user.clone(),
+            password: self.password.clone(),
+            password_file: self.password_file.clone(),
+        }
+    }
+}
+
+/// Settings which can differ between dashboards, unset ones fall back to the next level.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DashboardSettings {
+    /// Name of the Elasticsearch datasource the panels query.
+    pub datasource: Option<String>,
+    /// Id of the Grafana folder the dashboard is put into.
+    pub folder: Option<i64>,
+    /// Title of the folder in generated provisioning, Kubernetes and Terraform files.
+    pub folder_title: Option<String>,
+    /// Tags added to the ones marking converted dashboards.
+    pub tags: Option<Vec<String>>,
+    pub time: Option<TimeRange>,
+    /// Auto refresh interval like `1m`.
+    pub refresh: Option<String>,
+    /// Line color of single value sparklines, like `rgb(31, 120, 193)`.
+    pub sparkline_color: Option<String>,
+    /// Color of the area below single value sparklines.
+    pub sparkline_fill_color: Option<String>,
+}
+
+/// Settings for the dashboards matching `title` or the Graylog dashboard `id`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(from = "OverrideFields")]
+pub struct DashboardOverride {
+    pub title: Option<String>,
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub settings: DashboardSettings,
+}
+
+/// Fields of a `DashboardOverride`, serde ignores `deny_unknown_fields` for structs
+/// flattened into another.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverrideFields {
+    title: Option<String>,
+    id: Option<String>,
+    datasource: Option<String>,
+    folder: Option<i64>,
+    folder_title: Option<String>,
+    tags: Option<Vec<String>>,
+    time: Option<TimeRange>,
+    refresh: Option<String>,
+    sparkline_color: Option<String>,
+    sparkline_fill_color: Option<String>,
+}
+
+impl From<OverrideFields> for DashboardOverride {
+    fn from(fields: OverrideFields) -> DashboardOverride {
+        DashboardOverride {
+            title: fields.title,
+            id: fields.id,
+            settings: DashboardSettings {
+                datasource: fields.datasource,
+                folder: fields.folder,
+                folder_title: fields.folder_title,
+                tags: fields.tags,
+                time: fields.time,
+                refresh: fields.refresh,
+                sparkline_color: fields.sparkline_color,
+                sparkline_fill_color: fields.sparkline_fill_color,
+            },
+        }
+    }
+}
+
+impl Config {
+    /// Reads a `.toml` file, or YAML for all other extensions.
+    pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let config = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content)?,
+            _ => serde_yaml::from_str(&content)?,
+        };
+        Ok(config)
+    }
+}
+
+impl DashboardSettings {
+    /// Settings of `self` with those set in `other` taking precedence.
+    pub fn merge(self, other: &DashboardSettings) -> DashboardSettings {
+        let other = other.clone();
+        DashboardSettings {
+            datasource: other.datasource.or(self.datasource),
+            folder: other.folder.or(self.folder),
+            folder_title: other.folder_title.or(self.folder_title),
+            tags: other.tags.or(self.tags),
+            time: other.time.or(self.time),
+            refresh: other.refresh.or(self.refresh),
+            sparkline_color: other.sparkline_color.or(self.sparkline_color),
+            sparkline_fill_color: other.sparkline_fill_color.or(self.sparkline_fill_color),
+        }
+    }
+}
+
+impl DashboardOverride {
+    pub fn matches(&self, dashboard: &ir::Dashboard) -> bool {
+        self.title.as_ref() == Some(&dashboard.title)
+            || (self.id.is_some() && self.id == dashboard.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Action;
+
+    #[test]
+    fn load_toml_and_yaml() {
+        let dir = std::env::temp_dir().join("graylog-to-grafana-config-test");
+        fs::create_dir_all(&dir).unwrap();
+        let toml_path = dir.join("config.toml");
+        fs::write(
+            &toml_path,
+            r#"
+            [grafana]
+            url = "http://grafana:3000"
+            token_file = "/run/secrets/grafana"
+
+            [connection]
+            ca_cert = "/etc/ssl/internal-ca.pem"
+            timeout = 30
+
+            [policy]
+            strict = true
+            skipped_widget = "warn"
+
+            [defaults]
+            datasource = "elasticsearch"
+            time = { from = "now-7d", to = "now" }
+
+            [[dashboards]]
+            title = "Web"
+            folder = 3
+            tags = ["web"]
+            "#,
+        )
+        .unwrap();
+        let yaml_path = dir.join("config.yaml");
+        fs::write(
+            &yaml_path,
+            "grafana:\n  url: http://grafana:3000\n  token_file: /run/secrets/grafana\n\
+             connection:\n  ca_cert: /etc/ssl/internal-ca.pem\n  timeout: 30\n\
+             policy:\n  strict: true\n  skipped_widget: warn\n\
+             defaults:\n  datasource: elasticsearch\n  time: {from: now-7d, to: now}\n\
+             dashboards:\n  - title: Web\n    folder: 3\n    tags: [web]\n",
+        )
+        .unwrap();
+
+        for path in &[toml_path, yaml_path] {
+            let config = Config::load(path).unwrap();
+            assert_eq!(Some("http://grafana:3000".to_string()), config.grafana.url);
+            assert_eq!(
+                Some(Path::new("/run/secrets/grafana").to_path_buf()),
+                config.grafana.token_file
+            );
+            assert_eq!(
+                Some(Path::new("/etc/ssl/internal-ca.pem").to_path_buf()),
+                config.connection.ca_cert
+            );
+            assert_eq!(Some(30), config.connection.timeout);
+            assert_eq!(Some(true), config.policy.strict);
+            assert_eq!(Some(Action::Warn), config.policy.skipped_widget);
+            assert_eq!(
+                Some("elasticsearch".to_string()),
+                config.defaults.datasource
+            );
+            assert_eq!(Some(3), config.dashboards[0].settings.folder);
+            assert_eq!(
+                Some(vec!["web".to_string()]),
+                config.dashboards[0].settings.tags
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_server_fields_are_rejected() {
+        let config: Result<Config, _> = toml::from_str(
+            "[grafana]\nurl = \"http://grafana:3000\"\ntoken_fle = \"/run/secrets/grafana\"\n",
+        );
+        assert!(config.is_err());
+        let config: Result<Config, _> = serde_yaml::from_str("graylog:\n  pasword: secret\n");
+        assert!(config.is_err());
+        for typo in &[
+            "defaults:\n  refesh: 1m\n",
+            "dashboards:\n  - title: Web\n    sparkline_colour: red\n",
+            "connection:\n  time_out: 30\n",
+        ] {
+            assert!(serde_yaml::from_str::<Config>(typo).is_err(), "{}", typo);
+        }
+        let config: Config =
+            serde_yaml::from_str("graylog:\n  user: admin\n  password: secret\n").unwrap();
+        assert_eq!(Some("admin".to_string()), config.graylog.credentials().user);
+    }
+
+    #[test]
+    fn merge_prefers_other() {
+        let defaults = DashboardSettings {
+            datasource: Some("elasticsearch".to_string()),
+            folder: Some(1),
+            ..DashboardSettings::default()
+        };
+        let dashboard = DashboardSettings {
+            folder: Some(2),
+            refresh: Some("1m".to_string()),
+            ..DashboardSettings::default()
+        };
+
+        let merged = defaults.merge(&dashboard);
+
+        assert_eq!(Some("elasticsearch".to_string()), merged.datasource);
+        assert_eq!(Some(2), merged.folder);
+        assert_eq!(Some("1m".to_string()), merged.refresh);
+    }
+}
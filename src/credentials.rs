@@ -0,0 +1,137 @@
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Token or password, never shown by `Debug` so it can't end up in logs.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: &str) -> Secret {
+        Secret(secret.to_string())
+    }
+
+    /// Reads a secret from a file, ignoring the trailing newline most editors add.
+    pub fn from_file(path: &Path) -> Result<Secret, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Can't read secret from {}: {}", path.display(), e))?;
+        Ok(Secret(
+            content.trim_end_matches(&['\r', '\n'][..]).to_string(),
+        ))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl FromStr for Secret {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Secret, String> {
+        Ok(Secret::new(s))
+    }
+}
+
+/// How a client authenticates. Grafana sends tokens (service accounts, API keys) as bearer
+/// tokens, Graylog as user name with the password "token".
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    None,
+    Token(Secret),
+    Basic { user: String, password: Secret },
+}
+
+/// Places credentials can be read from, given on the command line, by environment
+/// variables or in the config file.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CredentialSources {
+    pub token: Option<Secret>,
+    pub token_file: Option<PathBuf>,
+    pub user: Option<String>,
+    pub password: Option<Secret>,
+    pub password_file: Option<PathBuf>,
+}
+
+impl CredentialSources {
+    pub fn is_empty(&self) -> bool {
+        self.token.is_none()
+            && self.token_file.is_none()
+            && self.user.is_none()
+            && self.password.is_none()
+            && self.password_file.is_none()
+    }
+
+    /// Basic auth if a user is given, otherwise a token given directly or by file.
+    pub fn credentials(&self) -> Result<Credentials, Box<dyn Error>> {
+        if let Some(user) = &self.user {
+            let password = match (&self.password, &self.password_file) {
+                (Some(password), _) => password.clone(),
+                (None, Some(path)) => Secret::from_file(path)?,
+                (None, None) => return Err(format!("No password given for user {}", user).into()),
+            };
+            return Ok(Credentials::Basic {
+                user: user.clone(),
+                password,
+            });
+        }
+        match (&self.token, &self.token_file) {
+            (Some(token), _) => Ok(Credentials::Token(token.clone())),
+            (None, Some(path)) => Ok(Credentials::Token(Secret::from_file(path)?)),
+            (None, None) => Ok(Credentials::None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_not_debug_printed() {
+        let credentials = Credentials::Basic {
+            user: "admin".to_string(),
+            password: Secret::new("hunter2"),
+        };
+
+        let printed = format!("{:?}", credentials);
+
+        assert!(printed.contains("admin"));
+        assert!(!printed.contains("hunter2"));
+    }
+
+    #[test]
+    fn credentials_from_sources() {
+        let path = std::env::temp_dir().join("graylog-to-grafana-token-test");
+        fs::write(&path, "glsa_abc\n").unwrap();
+        let from_file = CredentialSources {
+            token_file: Some(path),
+            ..CredentialSources::default()
+        };
+        let basic_without_password = CredentialSources {
+            user: Some("admin".to_string()),
+            token: Some(Secret::new("ignored")),
+            ..CredentialSources::default()
+        };
+
+        assert_eq!(
+            Credentials::Token(Secret::new("glsa_abc")),
+            from_file.credentials().unwrap()
+        );
+        assert!(basic_without_password.credentials().is_err());
+        assert_eq!(
+            Credentials::None,
+            CredentialSources::default().credentials().unwrap()
+        );
+    }
+}
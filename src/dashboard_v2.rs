@@ -0,0 +1,231 @@
+use crate::grafana::{Dashboard, Panel, PanelType};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Group and version of the resource API serving v2 dashboards.
+pub const API_VERSION: &str = "dashboard.grafana.app/v2alpha1";
+/// First Grafana release serving `API_VERSION`.
+pub const MIN_GRAFANA_VERSION: (u64, u64) = (12, 0);
+
+/// Settings of a v2 dashboard which aren't part of the converted dashboard.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceOptions {
+    /// Namespace of the Grafana organisation, `default` for the main organisation.
+    pub namespace: Option<String>,
+    pub folder_uid: Option<String>,
+    /// Uids of the datasources by name, v2 dashboards reference datasources by uid.
+    /// Names without uid are kept.
+    pub datasource_uids: HashMap<String, String>,
+}
+
+/// Dashboard resource in the v2 schema, which keeps the panels (`elements`) apart from
+/// their placement (`layout`).
+pub fn resource(dashboard: &Dashboard, opt: &ResourceOptions) -> Result<Value, Box<dyn Error>> {
+    let mut elements = Map::new();
+    let mut items = vec![];
+    for (i, panel) in dashboard.panels.iter().enumerate() {
+        let name = format!("panel-{}", i + 1);
+        elements.insert(name.clone(), element(panel, i + 1, opt)?);
+        items.push(json!({
+            "kind": "GridLayoutItem",
+            "spec": {
+                "x": panel.grid_pos.x,
+                "y": panel.grid_pos.y,
+                "width": panel.grid_pos.w,
+                "height": panel.grid_pos.h,
+                "element": {"kind": "ElementReference", "name": name},
+            },
+        }));
+    }
+    let annotations: Vec<Value> = dashboard
+        .annotations
+        .list
+        .iter()
+        .map(|annotation| {
+            json!({
+                "kind": "AnnotationQuery",
+                "spec": {
+                    "name": annotation.name,
+                    "datasource": datasource_ref(&annotation.datasource, opt),
+                    "query": {
+                        "kind": "elasticsearch",
+                        "spec": {
+                            "query": annotation.query,
+                            "timeField": annotation.time_field,
+                            "textField": annotation.text_field,
+                            "tagsField": annotation.tags_field,
+                        },
+                    },
+                    "enable": annotation.enable,
+                    "hide": false,
+                    "iconColor": annotation.icon_color,
+                },
+            })
+        })
+        .collect();
+
+    let mut metadata = json!({ "name": dashboard.uid });
+    if let Some(namespace) = &opt.namespace {
+        metadata["namespace"] = json!(namespace);
+    }
+    if let Some(folder_uid) = &opt.folder_uid {
+        metadata["annotations"] = json!({ "grafana.app/folder": folder_uid });
+    }
+    Ok(json!({
+        "apiVersion": API_VERSION,
+        "kind": "Dashboard",
+        "metadata": metadata,
+        "spec": {
+            "title": dashboard.title,
+            "description": "",
+            "tags": dashboard.tags,
+            "editable": true,
+            "preload": false,
+            "cursorSync": "Off",
+            "links": [],
+            "variables": [],
+            "timeSettings": {
+                "from": dashboard.time.from,
+                "to": dashboard.time.to,
+                "autoRefresh": dashboard.refresh.clone().unwrap_or_default(),
+                "autoRefreshIntervals": ["5s", "10s", "30s", "1m", "5m", "15m", "30m", "1h", "2h", "1d"],
+                "timezone": "browser",
+                "hideTimepicker": false,
+                "fiscalYearStartMonth": 0,
+            },
+            "annotations": annotations,
+            "elements": elements,
+            "layout": {"kind": "GridLayout", "spec": {"items": items}},
+        },
+    }))
+}
+
+fn element(panel: &Panel, id: usize, opt: &ResourceOptions) -> Result<Value, Box<dyn Error>> {
+    let datasource = datasource_ref(&panel.datasource, opt);
+    let mut queries = vec![];
+    for target in &panel.targets {
+        let mut spec = serde_json::to_value(target)?;
+        let ref_id = spec
+            .as_object_mut()
+            .and_then(|spec| spec.remove("refId"))
+            .unwrap_or_else(|| json!("A"));
+        queries.push(json!({
+            "kind": "PanelQuery",
+            "spec": {
+                "refId": ref_id,
+                "hidden": false,
+                "query": {"kind": "elasticsearch", "spec": spec},
+                "datasource": datasource,
+            },
+        }));
+    }
+    let mut query_options = json!({});
+    if let Some(time_from) = &panel.time_from {
+        query_options["timeFrom"] = json!(time_from);
+    }
+    let links: Vec<Value> = panel
+        .links
+        .iter()
+        .map(|link| json!({"title": link.title, "url": link.url, "targetBlank": link.target_blank}))
+        .collect();
+    Ok(json!({
+        "kind": "Panel",
+        "spec": {
+            "id": id,
+            "title": panel.title,
+            "description": "",
+            "links": links,
+            "data": {
+                "kind": "QueryGroup",
+                "spec": {
+                    "queries": queries,
+                    "transformations": [],
+                    "queryOptions": query_options,
+                },
+            },
+            "vizConfig": {
+                "kind": viz_kind(&panel.r#type),
+                "spec": {"options": {}, "fieldConfig": {"defaults": {}, "overrides": []}},
+            },
+        },
+    }))
+}
+
+fn datasource_ref(name: &str, opt: &ResourceOptions) -> Value {
+    let uid = opt.datasource_uids.get(name).map_or(name, String::as_str);
+    json!({"type": "elasticsearch", "uid": uid})
+}
+
+/// Graph and singlestat panels are only migrated by the v1 schema, v2 needs their successors.
+fn viz_kind(r#type: &PanelType) -> &'static str {
+    match r#type {
+        PanelType::Graph => "timeseries",
+        PanelType::SingleStat => "stat",
+        PanelType::PieChart => "piechart",
+    }
+}
+
+/// Whether a Grafana `version` as reported by `/api/health` serves v2 dashboards.
+pub fn supports_v2(version: &str) -> bool {
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|part| part.parse::<u64>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    (major, minor) >= MIN_GRAFANA_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grafana::{GridPos, PanelTarget};
+
+    #[test]
+    fn elements_and_layout_are_separate() {
+        let dashboard = Dashboard::new("Web").with_panel(
+            Panel::new(PanelType::SingleStat, "Errors", "graylog")
+                .with_target(PanelTarget::new_metric(
+                    "level:3",
+                    "auto",
+                    "B",
+                    String::new(),
+                    "count",
+                    "timestamp",
+                ))
+                .with_grid_pos(GridPos::new(2, 1, 4, 2)),
+        );
+        let mut datasource_uids = HashMap::new();
+        datasource_uids.insert("graylog".to_string(), "P1".to_string());
+        let opt = ResourceOptions {
+            folder_uid: Some("f1".to_string()),
+            datasource_uids,
+            ..ResourceOptions::default()
+        };
+
+        let resource = resource(&dashboard, &opt).unwrap();
+
+        assert_eq!("web-29751047", resource["metadata"]["name"]);
+        assert_eq!(
+            "f1",
+            resource["metadata"]["annotations"]["grafana.app/folder"]
+        );
+        let element = &resource["spec"]["elements"]["panel-1"]["spec"];
+        assert_eq!("stat", element["vizConfig"]["kind"]);
+        let query = &element["data"]["spec"]["queries"][0]["spec"];
+        assert_eq!("B", query["refId"]);
+        assert_eq!("P1", query["datasource"]["uid"]);
+        assert_eq!("level:3", query["query"]["spec"]["query"]);
+        let item = &resource["spec"]["layout"]["spec"]["items"][0]["spec"];
+        assert_eq!("panel-1", item["element"]["name"]);
+        assert_eq!(dashboard.panels[0].grid_pos.y, item["y"]);
+    }
+
+    #[test]
+    fn supports_v2_from_version() {
+        assert!(supports_v2("12.0.1"));
+        assert!(supports_v2("12.1.0-pre"));
+        assert!(!supports_v2("11.6.3"));
+        assert!(!supports_v2(""));
+    }
+}
@@ -0,0 +1,305 @@
+use crate::grafana;
+use serde_json::Value;
+use std::fmt;
+
+/// Panel level differences between a converted dashboard and the one stored in Grafana.
+#[derive(Debug)]
+pub struct DashboardDiff {
+    pub uid: String,
+    pub title: String,
+    pub exists: bool,
+    pub panels: Vec<PanelChange>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PanelChange {
+    Added(String),
+    Removed(String),
+    Changed {
+        title: String,
+        r#type: Option<(String, String)>,
+        targets: Vec<TargetChange>,
+        position: Option<(String, String)>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TargetChange {
+    Added(String),
+    Removed(String),
+    Changed(String),
+}
+
+impl DashboardDiff {
+    pub fn new(dashboard: &grafana::Dashboard, existing: Option<&Value>) -> DashboardDiff {
+        let converted = serde_json::to_value(dashboard).unwrap_or(Value::Null);
+        let panels = match existing {
+            Some(existing) => diff_panels(panels_of(existing), panels_of(&converted)),
+            None => panels_of(&converted)
+                .into_iter()
+                .map(|p| PanelChange::Added(title_of(p)))
+                .collect(),
+        };
+        DashboardDiff {
+            uid: dashboard.uid.clone(),
+            title: dashboard.title.clone(),
+            exists: existing.is_some(),
+            panels,
+        }
+    }
+}
+
+fn panels_of(dashboard: &Value) -> Vec<&Value> {
+    dashboard["panels"]
+        .as_array()
+        .map(|panels| panels.iter().collect())
+        .unwrap_or_default()
+}
+
+fn title_of(panel: &Value) -> String {
+    panel["title"].as_str().unwrap_or_default().to_string()
+}
+
+/// Pairs panels by title and position first, as several panels can share a title,
+/// and then by title alone.
+fn diff_panels(old: Vec<&Value>, new: Vec<&Value>) -> Vec<PanelChange> {
+    let mut remaining = old;
+    let mut take = |matches: &dyn Fn(&Value) -> bool| {
+        let index = remaining.iter().position(|p| matches(p))?;
+        Some(remaining.remove(index))
+    };
+    let in_place: Vec<_> = new
+        .iter()
+        .map(|panel| take(&|p| title_of(p) == title_of(panel) && p["gridPos"] == panel["gridPos"]))
+        .collect();
+    let mut changes = vec![];
+    for (panel, previous) in new.into_iter().zip(in_place) {
+        let title = title_of(panel);
+        match previous.or_else(|| take(&|p| title_of(p) == title)) {
+            Some(previous) => {
+                if let Some(change) = diff_panel(title, previous, panel) {
+                    changes.push(change);
+                }
+            }
+            None => changes.push(PanelChange::Added(title)),
+        }
+    }
+    changes.extend(
+        remaining
+            .into_iter()
+            .map(|p| PanelChange::Removed(title_of(p))),
+    );
+    changes
+}
+
+fn diff_panel(title: String, old: &Value, new: &Value) -> Option<PanelChange> {
+    let changed = |key: &str| {
+        if old[key] != new[key] {
+            Some((old[key].to_string(), new[key].to_string()))
+        } else {
+            None
+        }
+    };
+    let r#type = changed("type");
+    let position = changed("gridPos");
+    let targets = diff_targets(&old["targets"], &new["targets"]);
+    if r#type.is_none() && position.is_none() && targets.is_empty() {
+        return None;
+    }
+    Some(PanelChange::Changed {
+        title,
+        r#type,
+        targets,
+        position,
+    })
+}
+
+fn diff_targets(old: &Value, new: &Value) -> Vec<TargetChange> {
+    let empty = vec![];
+    let old = old.as_array().unwrap_or(&empty);
+    let new = new.as_array().unwrap_or(&empty);
+    let mut changes = vec![];
+    for i in 0..old.len().max(new.len()) {
+        let name = |t: &Value| format!("#{} {}", i + 1, t["query"].as_str().unwrap_or_default());
+        match (old.get(i), new.get(i)) {
+            (Some(o), Some(n)) if !same_target(o, n) => {
+                changes.push(TargetChange::Changed(name(n)))
+            }
+            (Some(o), None) => changes.push(TargetChange::Removed(name(o))),
+            (None, Some(n)) => changes.push(TargetChange::Added(name(n))),
+            _ => {}
+        }
+    }
+    changes
+}
+
+/// Compares the fields the converter writes. Grafana adds a datasource, `hide` and
+/// default metric and bucket settings to stored targets, which are ignored.
+fn same_target(old: &Value, new: &Value) -> bool {
+    let text = |value: &Value| match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let same_list = |key: &str, same: &dyn Fn(&Value, &Value) -> bool| {
+        let empty = vec![];
+        let old = old[key].as_array().unwrap_or(&empty);
+        let new = new[key].as_array().unwrap_or(&empty);
+        old.len() == new.len() && old.iter().zip(new).all(|(o, n)| same(o, n))
+    };
+    let same_keys =
+        |o: &Value, n: &Value, keys: &[&str]| keys.iter().all(|k| text(&o[*k]) == text(&n[*k]));
+    same_keys(old, new, &["query", "alias", "timeField"])
+        && same_list("metrics", &|o, n| same_keys(o, n, &["type", "field"]))
+        && same_list("bucketAggs", &|o, n| {
+            same_keys(o, n, &["type", "field"])
+                && n["settings"].as_object().map_or(true, |settings| {
+                    settings
+                        .iter()
+                        .all(|(key, value)| text(&o["settings"][key.as_str()]) == text(value))
+                })
+        })
+}
+
+impl fmt::Display for DashboardDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.exists {
+            writeln!(f, "+ dashboard \"{}\" (uid {})", self.title, self.uid)?;
+        } else if self.panels.is_empty() {
+            return writeln!(
+                f,
+                "  dashboard \"{}\" (uid {}) unchanged",
+                self.title, self.uid
+            );
+        } else {
+            writeln!(f, "~ dashboard \"{}\" (uid {})", self.title, self.uid)?;
+        }
+        for panel in &self.panels {
+            match panel {
+                PanelChange::Added(title) => writeln!(f, "    + panel \"{}\"", title)?,
+                PanelChange::Removed(title) => writeln!(f, "    - panel \"{}\"", title)?,
+                PanelChange::Changed {
+                    title,
+                    r#type,
+                    targets,
+                    position,
+                } => {
+                    writeln!(f, "    ~ panel \"{}\"", title)?;
+                    if let Some((old, new)) = r#type {
+                        writeln!(f, "        type: {} -> {}", old, new)?;
+                    }
+                    if let Some((old, new)) = position {
+                        writeln!(f, "        position: {} -> {}", old, new)?;
+                    }
+                    for target in targets {
+                        match target {
+                            TargetChange::Added(t) => writeln!(f, "        + target {}", t)?,
+                            TargetChange::Removed(t) => writeln!(f, "        - target {}", t)?,
+                            TargetChange::Changed(t) => writeln!(f, "        ~ target {}", t)?,
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grafana::PanelTarget;
+    use serde_json::json;
+
+    #[test]
+    fn diff_panels_detects_added_removed_and_moved_panels() {
+        let old = json!([
+            {"title": "kept", "type": "graph", "gridPos": {"x": 0}, "targets": []},
+            {"title": "removed", "type": "graph", "gridPos": {"x": 0}, "targets": []},
+        ]);
+        let new = json!([
+            {"title": "kept", "type": "graph", "gridPos": {"x": 5}, "targets": []},
+            {"title": "added", "type": "graph", "gridPos": {"x": 0}, "targets": []},
+        ]);
+        let changes = diff_panels(
+            old.as_array().unwrap().iter().collect(),
+            new.as_array().unwrap().iter().collect(),
+        );
+
+        assert_eq!(
+            vec![
+                PanelChange::Changed {
+                    title: "kept".to_string(),
+                    r#type: None,
+                    targets: vec![],
+                    position: Some(("{\"x\":0}".to_string(), "{\"x\":5}".to_string())),
+                },
+                PanelChange::Added("added".to_string()),
+                PanelChange::Removed("removed".to_string()),
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn diff_panels_pairs_repeated_titles_by_position() {
+        let old = json!([
+            {"title": "Count", "type": "singlestat", "gridPos": {"x": 0}, "targets": []},
+            {"title": "Count", "type": "graph", "gridPos": {"x": 5}, "targets": []},
+        ]);
+        let new = json!([
+            {"title": "Count", "type": "graph", "gridPos": {"x": 5}, "targets": []},
+            {"title": "Count", "type": "singlestat", "gridPos": {"x": 10}, "targets": []},
+        ]);
+        let changes = diff_panels(
+            old.as_array().unwrap().iter().collect(),
+            new.as_array().unwrap().iter().collect(),
+        );
+
+        assert_eq!(
+            vec![PanelChange::Changed {
+                title: "Count".to_string(),
+                r#type: None,
+                targets: vec![],
+                position: Some(("{\"x\":0}".to_string(), "{\"x\":10}".to_string())),
+            }],
+            changes
+        );
+    }
+
+    #[test]
+    fn diff_targets_compares_by_position() {
+        let old = json!([{"query": "a"}, {"query": "b"}]);
+        let new = json!([{"query": "a"}, {"query": "c"}, {"query": "d"}]);
+
+        assert_eq!(
+            vec![
+                TargetChange::Changed("#2 c".to_string()),
+                TargetChange::Added("#3 d".to_string()),
+            ],
+            diff_targets(&old, &new)
+        );
+    }
+
+    #[test]
+    fn diff_targets_ignores_grafana_defaults() {
+        let target = PanelTarget::new_buckets("source:web", "level", None, Some(10), "timestamp");
+        let new = json!([target]);
+        let mut stored = new.clone();
+        let normalised = &mut stored[0];
+        normalised["datasource"] = json!({"type": "elasticsearch", "uid": "P1"});
+        normalised["hide"] = json!(false);
+        normalised["metrics"][0]["settings"] = json!({});
+        normalised["bucketAggs"][0]["settings"]["min_doc_count"] = json!("10");
+        normalised["bucketAggs"][1]["settings"]["trimEdges"] = json!("0");
+        normalised["bucketAggs"][1]["settings"]["timeZone"] = json!("utc");
+
+        assert_eq!(Vec::<TargetChange>::new(), diff_targets(&stored, &new));
+
+        stored[0]["bucketAggs"][0]["field"] = json!("source");
+        assert_eq!(
+            vec![TargetChange::Changed("#1 source:web".to_string())],
+            diff_targets(&stored, &new)
+        );
+    }
+}
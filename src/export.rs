@@ -0,0 +1,197 @@
+use crate::grafana::{Dashboard, PanelType};
+use serde::Serialize;
+
+/// Grafana version the generated dashboards require, the oldest one knowing `gridPos`.
+pub const GRAFANA_VERSION: &str = "5.0.0";
+
+/// Dashboard as written by Grafana's "Export for sharing externally", with the
+/// datasources replaced by `${DS_...}` inputs which are chosen on import.
+#[derive(Serialize, Debug)]
+pub struct SharedDashboard {
+    #[serde(rename = "__inputs")]
+    pub inputs: Vec<Input>,
+    #[serde(rename = "__requires")]
+    pub requires: Vec<Requirement>,
+    #[serde(flatten)]
+    pub dashboard: Dashboard,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Input {
+    pub name: String,
+    pub label: String,
+    pub description: String,
+    pub r#type: String,
+    #[serde(rename = "pluginId")]
+    pub plugin_id: String,
+    #[serde(rename = "pluginName")]
+    pub plugin_name: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Requirement {
+    pub r#type: String,
+    pub id: String,
+    pub name: String,
+    pub version: String,
+}
+
+impl Requirement {
+    fn new(r#type: &str, id: &str, name: &str, version: &str) -> Requirement {
+        Requirement {
+            r#type: r#type.to_string(),
+            id: id.to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+}
+
+/// Value for an input of a shared dashboard, used by `/api/dashboards/import`.
+#[derive(Serialize, Debug, Clone)]
+pub struct InputValue {
+    pub name: String,
+    pub r#type: String,
+    #[serde(rename = "pluginId")]
+    pub plugin_id: String,
+    pub value: String,
+}
+
+/// Body of `/api/dashboards/import`.
+#[derive(Serialize, Debug)]
+pub struct ImportRequest {
+    pub dashboard: SharedDashboard,
+    pub overwrite: bool,
+    pub inputs: Vec<InputValue>,
+    #[serde(rename = "folderId")]
+    pub folder_id: i64,
+}
+
+impl SharedDashboard {
+    pub fn new(dashboard: &Dashboard) -> SharedDashboard {
+        let mut dashboard = dashboard.clone();
+        let mut datasources = vec![];
+        let mut placeholder = |datasource: &mut String| {
+            if !datasources.contains(datasource) {
+                datasources.push(datasource.clone());
+            }
+            *datasource = format!("${{{}}}", input_name(datasource));
+        };
+        for panel in &mut dashboard.panels {
+            placeholder(&mut panel.datasource);
+        }
+        for annotation in &mut dashboard.annotations.list {
+            placeholder(&mut annotation.datasource);
+        }
+
+        let inputs = datasources
+            .iter()
+            .map(|datasource| Input {
+                name: input_name(datasource),
+                label: datasource.clone(),
+                description: String::new(),
+                r#type: "datasource".to_string(),
+                plugin_id: "elasticsearch".to_string(),
+                plugin_name: "Elasticsearch".to_string(),
+            })
+            .collect();
+        let mut requires = vec![
+            Requirement::new("grafana", "grafana", "Grafana", GRAFANA_VERSION),
+            Requirement::new("datasource", "elasticsearch", "Elasticsearch", "1.0.0"),
+        ];
+        for panel in &dashboard.panels {
+            let (id, name) = panel_plugin(&panel.r#type);
+            if !requires.iter().any(|r| r.r#type == "panel" && r.id == id) {
+                requires.push(Requirement::new("panel", id, name, ""));
+            }
+        }
+        SharedDashboard {
+            inputs,
+            requires,
+            dashboard,
+        }
+    }
+}
+
+impl ImportRequest {
+    /// Imports the dashboard, binding every input to the datasource it replaced.
+    pub fn new(dashboard: &Dashboard, folder_id: i64) -> ImportRequest {
+        let shared = SharedDashboard::new(dashboard);
+        let inputs = shared
+            .inputs
+            .iter()
+            .map(|input| InputValue {
+                name: input.name.clone(),
+                r#type: input.r#type.clone(),
+                plugin_id: input.plugin_id.clone(),
+                value: input.label.clone(),
+            })
+            .collect();
+        ImportRequest {
+            dashboard: shared,
+            overwrite: true,
+            inputs,
+            folder_id,
+        }
+    }
+}
+
+/// Grafana names inputs after the datasource, e.g. `DS_GRAYLOG` for `graylog`.
+fn input_name(datasource: &str) -> String {
+    let name: String = datasource
+        .to_uppercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("DS_{}", name)
+}
+
+fn panel_plugin(r#type: &PanelType) -> (&'static str, &'static str) {
+    match r#type {
+        PanelType::Graph => ("graph", "Graph"),
+        PanelType::SingleStat => ("singlestat", "Singlestat"),
+        PanelType::PieChart => ("grafana-piechart-panel", "Pie Chart"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grafana::{Annotation, Panel};
+
+    #[test]
+    fn datasources_become_inputs() {
+        let dashboard = Dashboard::new("Web")
+            .with_panel(Panel::new(PanelType::PieChart, "Sources", "graylog"))
+            .with_panel(Panel::new(PanelType::PieChart, "Levels", "graylog"))
+            .with_annotation(Annotation::new_graylog_events("graylog-events", "*"));
+
+        let shared = SharedDashboard::new(&dashboard);
+
+        assert_eq!(
+            vec!["DS_GRAYLOG", "DS_GRAYLOG_EVENTS"],
+            shared
+                .inputs
+                .iter()
+                .map(|i| i.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("${DS_GRAYLOG}", shared.dashboard.panels[1].datasource);
+        assert_eq!(
+            "${DS_GRAYLOG_EVENTS}",
+            shared.dashboard.annotations.list[0].datasource
+        );
+        assert_eq!(
+            Requirement::new("panel", "grafana-piechart-panel", "Pie Chart", ""),
+            shared.requires[2]
+        );
+        assert_eq!(3, shared.requires.len());
+    }
+
+    #[test]
+    fn inputs_are_first_keys() {
+        let json = serde_json::to_string(&SharedDashboard::new(&Dashboard::new("Web"))).unwrap();
+
+        assert!(json.starts_with("{\"__inputs\":[],\"__requires\":[{\"type\":\"grafana\""));
+    }
+}
@@ -1,19 +1,81 @@
-use crate::{graylog, ApplicationArguments};
-use log::warn;
+use crate::{graylog, ir, ConversionOptions};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
 use url::form_urlencoded;
 
+/// Tag marking dashboards created by this tool, see `Dashboard::provenance_tags`.
+pub const PROVENANCE_TAG: &str = "graylog-to-grafana";
+/// Width of the dashboard grid.
+pub const GRID_COLUMNS: i64 = 24;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Dashboard {
+    pub uid: String,
     pub title: String,
-    panels: Vec<Panel>,
-    time: TimeRange,
+    pub tags: Vec<String>,
+    pub panels: Vec<Panel>,
+    pub time: TimeRange,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh: Option<String>,
+    #[serde(default, skip_serializing_if = "Annotations::is_empty")]
+    pub annotations: Annotations,
+    /// Grafana folder the dashboard is put into, not part of the dashboard json.
+    #[serde(skip)]
+    pub folder_id: i64,
+    /// Title of the folder in generated provisioning, Kubernetes and Terraform files, `None` for the General folder.
+    #[serde(skip)]
+    pub folder_title: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimeRange {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Annotations {
+    pub list: Vec<Annotation>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+/// Annotation query marking matching documents on every graph of the dashboard.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-struct TimeRange {
-    from: String,
-    to: String,
+pub struct Annotation {
+    pub name: String,
+    pub datasource: String,
+    pub enable: bool,
+    #[serde(rename = "iconColor")]
+    pub icon_color: String,
+    pub query: String,
+    #[serde(rename = "timeField")]
+    pub time_field: String,
+    #[serde(rename = "textField")]
+    pub text_field: String,
+    #[serde(rename = "tagsField")]
+    pub tags_field: String,
+}
+
+impl Annotation {
+    /// Events Graylog 3.1+ stores in its `gl-events_*` indices, `datasource` has to point there.
+    pub fn new_graylog_events(datasource: &str, query: &str) -> Annotation {
+        Annotation {
+            name: "Graylog events".to_string(),
+            datasource: datasource.to_string(),
+            enable: true,
+            icon_color: "rgba(255, 96, 96, 1)".to_string(),
+            query: query.to_string(),
+            time_field: "timestamp".to_string(),
+            text_field: "message".to_string(),
+            tags_field: "event_definition_id".to_string(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -25,23 +87,162 @@ pub struct ApiDashboard {
 }
 
 impl Dashboard {
-    pub fn create_dashboard_from_graylog(
-        dash: graylog::Dashboard,
-        opt: &ApplicationArguments,
-    ) -> Dashboard {
+    /// Empty dashboard showing the last two days, with a uid derived from `title`.
+    pub fn new(title: &str) -> Dashboard {
         Dashboard {
-            title: dash.title,
-            panels: dash
-                .dashboard_widgets
-                .into_iter()
-                .filter_map(|t| Panel::create_panel(t, opt))
-                .collect(),
+            uid: Dashboard::uid_from_title(title),
+            title: title.to_string(),
+            tags: vec![],
+            panels: vec![],
             time: TimeRange {
                 from: "now-2d".to_string(),
                 to: "now".to_string(),
             },
+            refresh: None,
+            annotations: Annotations::default(),
+            folder_id: 0,
+            folder_title: None,
         }
     }
+
+    pub fn with_uid(mut self, uid: &str) -> Dashboard {
+        self.uid = uid.to_string();
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Dashboard {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_panel(mut self, panel: Panel) -> Dashboard {
+        self.panels.push(panel);
+        self
+    }
+
+    pub fn with_time_range(mut self, from: &str, to: &str) -> Dashboard {
+        self.time = TimeRange {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        self
+    }
+
+    pub fn with_refresh(mut self, refresh: &str) -> Dashboard {
+        self.refresh = Some(refresh.to_string());
+        self
+    }
+
+    pub fn with_folder_id(mut self, folder_id: i64) -> Dashboard {
+        self.folder_id = folder_id;
+        self
+    }
+
+    pub fn with_folder_title(mut self, folder_title: &str) -> Dashboard {
+        self.folder_title = Some(folder_title.to_string());
+        self
+    }
+
+    /// Name of the file the dashboard json is written to.
+    pub fn file_name(&self) -> String {
+        Dashboard::file_name_for(&self.title)
+    }
+
+    /// Name of the file a dashboard titled `title` is written to, in every output format.
+    pub fn file_name_for(title: &str) -> String {
+        format!(
+            "{}.json",
+            title.replace(" ", "_").replace("/", "_").to_lowercase()
+        )
+    }
+
+    pub fn with_annotation(mut self, annotation: Annotation) -> Dashboard {
+        self.annotations.list.push(annotation);
+        self
+    }
+
+    /// Grafana dashboard for a dashboard of the intermediate representation, with the
+    /// settings `opt` has for it.
+    pub fn from_ir(
+        dash: &ir::Dashboard,
+        content_pack: &str,
+        opt: &ConversionOptions,
+    ) -> Result<Dashboard, Box<dyn Error>> {
+        let settings = opt.settings_for(dash);
+        let opt = &ConversionOptions {
+            datasource: settings
+                .datasource
+                .unwrap_or_else(|| opt.datasource.clone()),
+            ..opt.clone()
+        };
+        let mut tags = Dashboard::provenance_tags(content_pack);
+        tags.extend(settings.tags.unwrap_or_default());
+        let mut dashboard = Dashboard::new(&dash.title)
+            .with_tags(tags)
+            .with_folder_id(settings.folder.unwrap_or(0));
+        if let Some(folder_title) = settings.folder_title {
+            dashboard = dashboard.with_folder_title(&folder_title);
+        }
+        if let Some(time) = settings.time {
+            dashboard = dashboard.with_time_range(&time.from, &time.to);
+        }
+        if let Some(refresh) = settings.refresh {
+            dashboard = dashboard.with_refresh(&refresh);
+        }
+        for panel in &dash.panels {
+            let mut panel = Panel::from_ir(panel, opt);
+            if let Some(sparkline) = &mut panel.sparkline {
+                if let Some(color) = &settings.sparkline_color {
+                    sparkline.line_color = color.clone();
+                }
+                if let Some(color) = &settings.sparkline_fill_color {
+                    sparkline.fill_color = color.clone();
+                }
+            }
+            dashboard = dashboard.with_panel(panel);
+        }
+        if let Some(datasource) = &opt.events_datasource {
+            dashboard = dashboard.with_annotation(Annotation::new_graylog_events(
+                datasource,
+                &opt.events_query,
+            ));
+        }
+        Ok(dashboard)
+    }
+
+    /// Tags identifying dashboards converted from a content pack, used to find
+    /// dashboards which no longer exist in Graylog.
+    pub fn provenance_tags(content_pack: &str) -> Vec<String> {
+        vec![PROVENANCE_TAG.to_string(), content_pack.to_string()]
+    }
+
+    /// Hash over the generated dashboard json and the folder it goes to, used to detect
+    /// changes between runs.
+    pub fn content_hash(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        let hash = Sha256::new()
+            .chain(&json)
+            .chain(self.folder_id.to_string())
+            .result();
+        format!("{:x}", hash)
+    }
+
+    /// Grafana uids are limited to 40 characters of `[a-zA-Z0-9-_]`, deriving
+    /// them from the title keeps them stable between runs. Titles which have to be
+    /// shortened, lowercased or have characters replaced get a hash of the title appended,
+    /// so they don't end up with the same uid.
+    pub fn uid_from_title(title: &str) -> String {
+        let lowercase = title.to_lowercase();
+        let uid: String = lowercase
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        if uid == title && uid.len() <= 40 {
+            return uid;
+        }
+        let hash = format!("{:x}", Sha256::new().chain(title).result());
+        format!("{}-{}", &uid[..uid.len().min(31)], &hash[..8])
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -54,20 +255,40 @@ pub enum PanelType {
     PieChart,
 }
 
+impl PanelType {
+    pub fn from_ir(visualization: &ir::Visualization) -> PanelType {
+        match visualization {
+            ir::Visualization::SingleValue { .. } => PanelType::SingleStat,
+            ir::Visualization::Chart { .. } => PanelType::Graph,
+            ir::Visualization::Pie => PanelType::PieChart,
+        }
+    }
+
+    /// Panel plugin id, as in the dashboard json.
+    pub fn id(&self) -> &'static str {
+        match self {
+            PanelType::Graph => "graph",
+            PanelType::SingleStat => "singlestat",
+            PanelType::PieChart => "grafana-piechart-panel",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Link {
-    title: String,
-    r#type: String,
-    url: String,
+    pub title: String,
+    pub r#type: String,
+    pub url: String,
     #[serde(rename = "targetBlank")]
-    target_blank: bool,
+    pub target_blank: bool,
 }
 
 impl Link {
-    fn new(url: &str, query: &str, seconds: i64) -> Link {
+    /// Link to the Graylog search for `query` over the last `seconds`, showing `fields`.
+    pub fn new_graylog_search(url: &str, query: &str, seconds: i64, fields: &str) -> Link {
         let encoded: String = form_urlencoded::Serializer::new(String::new())
             .append_pair("rangetype", "relative")
-            .append_pair("fields", "message,source")
+            .append_pair("fields", fields)
             .append_pair("width", "1920")
             .append_pair("highlightMessage", "")
             .append_pair("relative", &seconds.to_string())
@@ -84,207 +305,180 @@ impl Link {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Panel {
-    r#type: PanelType,
-    title: String,
-    links: Vec<Link>,
-    datasource: String,
-    targets: Vec<PanelTarget>,
+    pub r#type: PanelType,
+    pub title: String,
+    pub links: Vec<Link>,
+    pub datasource: String,
+    pub targets: Vec<PanelTarget>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    bars: Option<bool>,
+    pub bars: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    lines: Option<bool>,
+    pub lines: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    points: Option<bool>,
+    pub points: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    sparkline: Option<Sparkline>,
+    pub sparkline: Option<Sparkline>,
     #[serde(rename = "gridPos")]
-    grid_pos: GridPos,
+    pub grid_pos: GridPos,
     #[serde(rename = "valueName", skip_serializing_if = "Option::is_none")]
-    value_name: Option<String>,
+    pub value_name: Option<String>,
     #[serde(rename = "timeFrom", skip_serializing_if = "Option::is_none")]
-    time_from: Option<String>,
+    pub time_from: Option<String>,
 }
 
 impl Panel {
-    fn new_graph(
-        title: String,
-        targets: Vec<PanelTarget>,
-        renderer: graylog::ChartRenderer,
-        grid_pos: GridPos,
-        opt: &ApplicationArguments,
-    ) -> Panel {
+    /// Panel without targets in the top left corner.
+    pub fn new(r#type: PanelType, title: &str, datasource: &str) -> Panel {
         Panel {
-            title,
-            r#type: PanelType::Graph,
-            datasource: opt.datasource.clone(),
-            targets,
-            bars: Some(renderer == graylog::ChartRenderer::Bar),
-            lines: Some(
-                renderer == graylog::ChartRenderer::Line
-                    || renderer == graylog::ChartRenderer::Area,
-            ),
-            points: Some(renderer == graylog::ChartRenderer::Area),
+            r#type,
+            title: title.to_string(),
+            links: vec![],
+            datasource: datasource.to_string(),
+            targets: vec![],
+            bars: None,
+            lines: None,
+            points: None,
             sparkline: None,
-            grid_pos,
+            grid_pos: GridPos::new(1, 1, 1, 1),
             value_name: None,
             time_from: None,
-            links: vec![],
         }
     }
 
-    fn new(
-        title: String,
-        r#type: PanelType,
-        query: &str,
-        sparkline: Option<Sparkline>,
-        targets: Vec<PanelTarget>,
-        grid_pos: GridPos,
-        range: i64,
-        opt: &ApplicationArguments,
-    ) -> Panel {
-        Panel {
-            title,
-            r#type,
-            value_name: Some("total".to_string()),
-            datasource: opt.datasource.clone(),
-            targets,
-            bars: None,
-            lines: None,
-            points: None,
-            sparkline,
-            grid_pos,
-            time_from: Some(format!("{}h", range / 3600)),
-            links: vec![Link::new(&opt.graylog_url, query, range)],
-        }
+    pub fn with_target(mut self, target: PanelTarget) -> Panel {
+        self.targets.push(target);
+        self
     }
 
-    pub fn create_panel(
-        widget: graylog::DashboardWidget,
-        opt: &ApplicationArguments,
-    ) -> Option<Panel> {
-        let grid_pos = GridPos::new_with_widget(&widget);
-
-        let panel = match widget.r#type {
-            graylog::DashboardWidgetType::FieldChart => {
-                let configuration = widget.configuration;
-                Panel::new_graph(
-                    widget.description,
-                    vec![PanelTarget::new(
-                        &configuration.query.unwrap(),
-                        configuration.interval.unwrap().grafana(),
-                        "A",
-                        configuration.field.unwrap(),
-                        configuration.valuetype.unwrap(),
-                    )],
-                    configuration.renderer.unwrap(),
-                    grid_pos,
-                    opt,
-                )
-            }
-            graylog::DashboardWidgetType::StackedChart => {
-                let configuration = widget.configuration;
-                let interval = configuration.interval.unwrap().grafana();
-                Panel::new_graph(
-                    widget.description,
-                    configuration
-                        .series
-                        .unwrap()
-                        .iter()
-                        .map(|s| {
-                            PanelTarget::new(
-                                &s.query,
-                                interval.to_string(),
-                                "A",
-                                s.field.clone(),
-                                s.statistical_function.clone(),
-                            )
-                        })
-                        .collect(),
-                    configuration.renderer.unwrap(),
-                    grid_pos,
-                    opt,
-                )
-            }
-            graylog::DashboardWidgetType::SearchResultCount => {
-                let configuration = widget.configuration;
-                let query = &configuration.query.unwrap();
-                Panel::new(
-                    widget.description,
-                    PanelType::SingleStat,
-                    query,
-                    Some(Sparkline::new(configuration.trend.unwrap())),
-                    vec![PanelTarget::new(
-                        query,
-                        "1m",
-                        "A",
-                        "select field".to_string(),
-                        "count".to_string(),
-                    )],
-                    grid_pos,
-                    configuration.timerange.range,
-                    opt,
-                )
-            }
-            graylog::DashboardWidgetType::SearchResultChart => {
-                let configuration = widget.configuration;
-                Panel::new_graph(
-                    widget.description,
-                    vec![PanelTarget::new(
-                        &configuration.query.unwrap(),
-                        configuration.interval.unwrap().grafana(),
-                        "A",
-                        "select field".to_string(),
-                        "count".to_string(),
-                    )],
-                    graylog::ChartRenderer::Bar,
-                    grid_pos,
-                    opt,
-                )
-            }
-            graylog::DashboardWidgetType::QuickValues => {
-                let configuration = widget.configuration;
-                let query = &configuration.query.unwrap();
-                Panel::new(
-                    widget.description,
-                    PanelType::PieChart,
-                    query,
-                    None,
-                    vec![PanelTarget::new_buckets(
-                        query,
-                        &configuration.field.unwrap(),
-                        configuration.sort_order,
-                        configuration.limit,
-                    )],
-                    grid_pos,
-                    configuration.timerange.range,
-                    opt,
-                )
+    pub fn with_grid_pos(mut self, grid_pos: GridPos) -> Panel {
+        self.grid_pos = grid_pos;
+        self
+    }
+
+    pub fn with_link(mut self, link: Link) -> Panel {
+        self.links.push(link);
+        self
+    }
+
+    pub fn with_sparkline(mut self, sparkline: Sparkline) -> Panel {
+        self.sparkline = Some(sparkline);
+        self
+    }
+
+    pub fn with_value_name(mut self, value_name: &str) -> Panel {
+        self.value_name = Some(value_name.to_string());
+        self
+    }
+
+    /// Overrides the dashboard time range with the last `seconds`, rounded down to hours.
+    pub fn with_relative_time(mut self, seconds: i64) -> Panel {
+        self.time_from = Some(format!("{}h", seconds / 3600));
+        self
+    }
+
+    /// Draws a graph panel as bars, lines or lines with points like the Graylog chart.
+    pub fn with_renderer(mut self, renderer: graylog::ChartRenderer) -> Panel {
+        self.bars = Some(renderer == graylog::ChartRenderer::Bar);
+        self.lines = Some(
+            renderer == graylog::ChartRenderer::Line || renderer == graylog::ChartRenderer::Area,
+        );
+        self.points = Some(renderer == graylog::ChartRenderer::Area);
+        self
+    }
+
+    /// Panel for a panel of the intermediate representation. Single values and pie charts
+    /// show the total of their time range, charts follow the dashboard time range.
+    pub fn from_ir(panel: &ir::Panel, opt: &ConversionOptions) -> Panel {
+        let r#type = PanelType::from_ir(&panel.visualization);
+        let grafana = panel
+            .queries
+            .iter()
+            .map(|query| PanelTarget::from_ir(query, &opt.time_field))
+            .fold(
+                Panel::new(r#type, &panel.title, &opt.datasource),
+                Panel::with_target,
+            )
+            .with_grid_pos(GridPos::new_with_layout(&panel.layout));
+        let grafana = match panel.visualization {
+            ir::Visualization::Chart { style } => {
+                return grafana.with_renderer(match style {
+                    ir::ChartStyle::Bar => graylog::ChartRenderer::Bar,
+                    ir::ChartStyle::Line => graylog::ChartRenderer::Line,
+                    ir::ChartStyle::Area => graylog::ChartRenderer::Area,
+                })
             }
-            graylog::DashboardWidgetType::QuickValuesHistogram => {
-                warn!(
-                    "Not Supported {:?} graph: {}",
-                    graylog::DashboardWidgetType::QuickValuesHistogram,
-                    widget.description
-                );
-                return None;
+            ir::Visualization::SingleValue { trend } => {
+                grafana.with_sparkline(Sparkline::new(trend))
             }
+            ir::Visualization::Pie => grafana,
         };
-        Some(panel)
+        match (panel.queries.first(), panel.time_range) {
+            (Some(query), Some(range)) => grafana.with_graylog_search(&query.query, range, opt),
+            _ => grafana,
+        }
+    }
+
+    /// Where the panel `from_ir` creates for `panel` deviates from it, in addition to
+    /// the warnings `panel` already has.
+    pub fn deviations(panel: &ir::Panel) -> Vec<ir::Warning> {
+        let mut warnings = vec![];
+        let mut warn = |kind, message: String| warnings.push(ir::Warning { kind, message });
+        match (&panel.visualization, panel.time_range) {
+            (ir::Visualization::Chart { .. }, Some(range)) => warn(
+                ir::WarningKind::TimeRange,
+                format!(
+                    "time range of {}s replaced by the dashboard time range",
+                    range
+                ),
+            ),
+            (_, Some(range)) if range % 3600 != 0 => warn(
+                ir::WarningKind::TimeRange,
+                format!("time range of {}s rounded down to {}h", range, range / 3600),
+            ),
+            _ => {}
+        }
+        let grid_pos = GridPos::new_with_layout(&panel.layout);
+        if grid_pos.x + grid_pos.w > GRID_COLUMNS {
+            warn(
+                ir::WarningKind::LayoutOverflow,
+                format!(
+                    "ends at column {} of the {} grid columns",
+                    grid_pos.x + grid_pos.w,
+                    GRID_COLUMNS
+                ),
+            );
+        }
+        warnings
+    }
+
+    /// Settings shared by panels showing the result of a single Graylog search over
+    /// the last `range` seconds, linking to that search if a Graylog url is configured.
+    pub fn with_graylog_search(self, query: &str, range: i64, opt: &ConversionOptions) -> Panel {
+        let mut panel = self.with_value_name("total").with_relative_time(range);
+        if let Some(url) = &opt.graylog_url {
+            panel = panel.with_link(Link::new_graylog_search(
+                url,
+                query,
+                range,
+                &opt.link_fields,
+            ));
+        }
+        panel
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Sparkline {
-    show: bool,
-    full: bool,
+    pub show: bool,
+    pub full: bool,
     #[serde(rename = "lineColor")]
-    line_color: String,
+    pub line_color: String,
     #[serde(rename = "fillColor")]
-    fill_color: String,
+    pub fill_color: String,
 }
 
 impl Sparkline {
-    fn new(trend: bool) -> Sparkline {
+    pub fn new(trend: bool) -> Sparkline {
         Sparkline {
             show: trend,
             full: false,
@@ -296,17 +490,19 @@ impl Sparkline {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GridPos {
-    h: i64,
-    w: i64,
-    y: i64,
-    x: i64,
+    pub h: i64,
+    pub w: i64,
+    pub y: i64,
+    pub x: i64,
 }
 
 impl GridPos {
-    fn new_with_widget(widget: &graylog::DashboardWidget) -> GridPos {
-        GridPos::new(widget.row, widget.col, widget.width, widget.height)
+    pub fn new_with_layout(layout: &ir::Layout) -> GridPos {
+        GridPos::new(layout.row, layout.col, layout.width, layout.height)
     }
-    fn new(row: i64, col: i64, width: i64, height: i64) -> GridPos {
+
+    /// Position of a widget on Graylog's grid (1-based columns) translated to Grafana's.
+    pub fn new(row: i64, col: i64, width: i64, height: i64) -> GridPos {
         GridPos {
             h: height * 6,
             w: width * 5,
@@ -319,24 +515,44 @@ impl GridPos {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PanelTarget {
     #[serde(rename = "refId")]
-    ref_id: String,
-    metrics: Vec<PanelTargetMetric>,
+    pub ref_id: String,
+    pub metrics: Vec<PanelTargetMetric>,
     #[serde(rename = "bucketAggs")]
-    bucket_aggs: Vec<PanelBucketAgg>,
+    pub bucket_aggs: Vec<PanelBucketAgg>,
     #[serde(rename = "timeField")]
-    time_field: String,
-    query: String,
+    pub time_field: String,
+    pub query: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    alias: Option<String>,
+    pub alias: Option<String>,
 }
 
 impl PanelTarget {
-    fn new<T1, T2>(
+    /// Target for a Graylog `valuetype`, `count` counts messages and everything else sums `field`.
+    pub fn new<T1, T2>(
         query: &str,
         interval: T1,
         ref_id: T2,
         field: String,
         valuetype: String,
+        time_field: &str,
+    ) -> PanelTarget
+    where
+        T1: Into<String>,
+        T2: Into<String>,
+    {
+        let metric = if valuetype == "count" { "count" } else { "sum" };
+        PanelTarget::new_metric(query, interval, ref_id, field, metric, time_field)
+    }
+
+    /// Target with a single Elasticsearch `metric` aggregation over a date histogram
+    /// on `time_field`.
+    pub fn new_metric<T1, T2>(
+        query: &str,
+        interval: T1,
+        ref_id: T2,
+        field: String,
+        metric: &str,
+        time_field: &str,
     ) -> PanelTarget
     where
         T1: Into<String>,
@@ -345,26 +561,28 @@ impl PanelTarget {
         PanelTarget {
             ref_id: ref_id.into(),
             metrics: vec![PanelTargetMetric {
-                r#type: if valuetype == "count" {
-                    "count".to_string()
-                } else {
-                    "sum".to_string()
-                },
+                r#type: metric.to_string(),
                 id: "1".to_string(),
                 field,
             }],
-            bucket_aggs: vec![PanelBucketAgg::new_date_histogram(interval.into(), None)],
-            time_field: "timestamp".to_string(),
+            bucket_aggs: vec![PanelBucketAgg::new_date_histogram(
+                time_field,
+                interval.into(),
+                None,
+            )],
+            time_field: time_field.to_string(),
             query: query.to_string(),
             alias: Some(query.to_string()),
         }
     }
 
-    fn new_buckets(
+    /// Pie chart target counting messages per value of `field`.
+    pub fn new_buckets(
         query: &str,
         field: &str,
         sort_order: Option<String>,
         limit: Option<i64>,
+        time_field: &str,
     ) -> PanelTarget {
         PanelTarget {
             ref_id: "A".to_string(),
@@ -379,33 +597,86 @@ impl PanelTarget {
                     sort_order.or_else(|| Some("desc".to_string())),
                     limit.unwrap_or(5),
                 ),
-                PanelBucketAgg::new_date_histogram("1h".to_string(), true),
+                PanelBucketAgg::new_date_histogram(time_field, "1h".to_string(), true),
             ],
-            time_field: "timestamp".to_string(),
+            time_field: time_field.to_string(),
             query: query.to_string(),
             alias: None,
         }
     }
+
+    /// Target for a query of the intermediate representation. Terms buckets count
+    /// messages per value like `new_buckets`, everything else is a metric over time.
+    pub fn from_ir(query: &ir::Query, time_field: &str) -> PanelTarget {
+        for bucket in &query.buckets {
+            if let ir::Bucket::Terms { field, order, size } = bucket {
+                return PanelTarget::new_buckets(
+                    &query.query,
+                    field,
+                    Some(order.clone()),
+                    Some(*size),
+                    time_field,
+                );
+            }
+        }
+        let interval = query
+            .buckets
+            .iter()
+            .find_map(|bucket| match bucket {
+                ir::Bucket::DateHistogram { interval } => Some(interval.as_str()),
+                _ => None,
+            })
+            .unwrap_or("auto");
+        let field = query
+            .metric
+            .field
+            .clone()
+            .unwrap_or_else(|| "select field".to_string());
+        PanelTarget::new_metric(
+            &query.query,
+            interval,
+            "A",
+            field,
+            query.metric.function.aggregation(),
+            time_field,
+        )
+    }
+
+    pub fn with_alias(mut self, alias: Option<String>) -> PanelTarget {
+        self.alias = alias;
+        self
+    }
+
+    /// Moves the target and its date histograms to `time_field`.
+    pub fn with_time_field(mut self, time_field: &str) -> PanelTarget {
+        self.time_field = time_field.to_string();
+        for bucket in &mut self.bucket_aggs {
+            if bucket.r#type == "date_histogram" {
+                bucket.field = time_field.to_string();
+            }
+        }
+        self
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PanelTargetMetric {
-    r#type: String,
-    id: String,
-    field: String,
+    pub r#type: String,
+    pub id: String,
+    pub field: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PanelBucketAgg {
-    r#type: String,
-    id: String,
-    settings: PanelBucketAggSettings,
-    field: String,
+    pub r#type: String,
+    pub id: String,
+    pub settings: PanelBucketAggSettings,
+    pub field: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    fake: Option<bool>,
+    pub fake: Option<bool>,
 }
 impl PanelBucketAgg {
-    fn new_date_histogram<T1, T2>(interval: T1, fake: T2) -> PanelBucketAgg
+    pub fn new_date_histogram<T1, T2>(time_field: &str, interval: T1, fake: T2) -> PanelBucketAgg
     where
         T1: Into<Option<String>>,
         T2: Into<Option<bool>>,
@@ -421,11 +692,11 @@ impl PanelBucketAgg {
                 trim_edges: 0,
                 order_by: None,
             },
-            field: "timestamp".to_string(),
+            field: time_field.to_string(),
             fake: fake.into(),
         }
     }
-    fn new_terms(field: &str, order: Option<String>, limit: i64) -> PanelBucketAgg {
+    pub fn new_terms(field: &str, order: Option<String>, limit: i64) -> PanelBucketAgg {
         PanelBucketAgg {
             r#type: "terms".to_string(),
             field: field.to_string(),
@@ -446,16 +717,16 @@ impl PanelBucketAgg {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PanelBucketAggSettings {
     #[serde(skip_serializing_if = "Option::is_none")]
-    interval: Option<String>,
+    pub interval: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    order: Option<String>,
+    pub order: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    size: Option<String>,
-    min_doc_count: i64,
+    pub size: Option<String>,
+    pub min_doc_count: i64,
     #[serde(rename = "trimEdges")]
-    trim_edges: i64,
+    pub trim_edges: i64,
     #[serde(rename = "orderBy", skip_serializing_if = "Option::is_none")]
-    order_by: Option<String>,
+    pub order_by: Option<String>,
 }
 #[cfg(test)]
 mod tests {
@@ -463,7 +734,7 @@ mod tests {
 
     #[test]
     fn panel_bucket_agg_new_date_histogram_with_fake_interval() {
-        let bucket = PanelBucketAgg::new_date_histogram("".to_string(), true);
+        let bucket = PanelBucketAgg::new_date_histogram("timestamp", "".to_string(), true);
 
         assert_eq!(Some(true), bucket.fake);
         assert_eq!(Some("".to_string()), bucket.settings.interval);
@@ -471,9 +742,143 @@ mod tests {
 
     #[test]
     fn panel_bucket_agg_new_date_histogram_without_fake_interval() {
-        let bucket = PanelBucketAgg::new_date_histogram(None, None);
+        let bucket = PanelBucketAgg::new_date_histogram("timestamp", None, None);
 
         assert_eq!(None, bucket.fake);
         assert_eq!(None, bucket.settings.interval);
     }
+
+    #[test]
+    fn dashboard_uid_from_title() {
+        assert_eq!(
+            "web-frontend--errors-a3ff9fd3",
+            Dashboard::uid_from_title("Web Frontend: Errors")
+        );
+        assert_ne!(
+            Dashboard::uid_from_title("Web Frontend: Errors"),
+            Dashboard::uid_from_title("Web Frontend/ Errors")
+        );
+        assert_eq!("web-errors", Dashboard::uid_from_title("web-errors"));
+        assert_ne!(
+            Dashboard::uid_from_title("Errors"),
+            Dashboard::uid_from_title("errors")
+        );
+        let long = Dashboard::uid_from_title(&"a".repeat(60));
+        assert_eq!(40, long.len());
+        assert_ne!(long, Dashboard::uid_from_title(&"a".repeat(61)));
+    }
+
+    #[test]
+    fn dashboard_content_hash_covers_folder() {
+        let dashboard = Dashboard::new("Web");
+
+        let moved = dashboard.clone().with_folder_id(12);
+
+        assert_eq!(
+            dashboard.content_hash(),
+            Dashboard::new("Web").content_hash()
+        );
+        assert_ne!(dashboard.content_hash(), moved.content_hash());
+    }
+
+    #[test]
+    fn dashboard_with_annotation() {
+        let dashboard = Dashboard::new("a");
+        assert!(serde_json::to_value(&dashboard).unwrap()["annotations"].is_null());
+
+        let dashboard = dashboard.with_annotation(Annotation::new_graylog_events(
+            "graylog-events",
+            "alert:true",
+        ));
+
+        let json = serde_json::to_value(&dashboard).unwrap();
+        assert_eq!(
+            "graylog-events",
+            json["annotations"]["list"][0]["datasource"]
+        );
+        assert_eq!("alert:true", json["annotations"]["list"][0]["query"]);
+    }
+
+    #[test]
+    fn dashboard_builder() {
+        let dashboard = Dashboard::new("Web: Errors")
+            .with_time_range("now-7d", "now")
+            .with_panel(
+                Panel::new(PanelType::Graph, "Errors", "elasticsearch")
+                    .with_target(PanelTarget::new_metric(
+                        "level:3",
+                        "1h",
+                        "A",
+                        "select field".to_string(),
+                        "count",
+                        "timestamp",
+                    ))
+                    .with_renderer(graylog::ChartRenderer::Line)
+                    .with_grid_pos(GridPos::new(0, 1, 2, 1)),
+            );
+
+        let json = serde_json::to_value(&dashboard).unwrap();
+        assert_eq!("web--errors-0f0b2830", json["uid"]);
+        assert_eq!("now-7d", json["time"]["from"]);
+        assert_eq!(
+            serde_json::json!({"h": 6, "w": 10, "y": 0, "x": 0}),
+            json["panels"][0]["gridPos"]
+        );
+        assert_eq!(true, json["panels"][0]["lines"]);
+        assert_eq!("level:3", json["panels"][0]["targets"][0]["query"]);
+    }
+
+    #[test]
+    fn dashboard_from_ir_with_sparkline_colors() {
+        let opt = ConversionOptions {
+            defaults: crate::config::DashboardSettings {
+                sparkline_color: Some("#73bf69".to_string()),
+                ..crate::config::DashboardSettings::default()
+            },
+            ..ConversionOptions::default()
+        };
+        let dash = ir::Dashboard::new("Web").with_panel(ir::Panel::new(
+            "Errors",
+            ir::Visualization::SingleValue { trend: true },
+        ));
+
+        let dashboard = Dashboard::from_ir(&dash, "web", &opt).unwrap();
+
+        let sparkline = dashboard.panels[0].sparkline.as_ref().unwrap();
+        assert_eq!("#73bf69", sparkline.line_color);
+        assert_eq!("rgba(31, 118, 189, 0.18)", sparkline.fill_color);
+    }
+
+    #[test]
+    fn panel_from_ir_buckets_on_time_field() {
+        let opt = ConversionOptions {
+            time_field: "@timestamp".to_string(),
+            ..ConversionOptions::default()
+        };
+        let chart = ir::Panel::new(
+            "Errors",
+            ir::Visualization::Chart {
+                style: ir::ChartStyle::Line,
+            },
+        )
+        .with_query(ir::Query::new_count("level:3"));
+        let pie = ir::Panel::new("Levels", ir::Visualization::Pie).with_query(
+            ir::Query::new_count("*").with_bucket(ir::Bucket::Terms {
+                field: "level".to_string(),
+                order: "desc".to_string(),
+                size: 5,
+            }),
+        );
+
+        for panel in &[chart, pie] {
+            let target = &Panel::from_ir(panel, &opt).targets[0];
+            assert_eq!("@timestamp", target.time_field);
+            let histogram = target
+                .bucket_aggs
+                .iter()
+                .find(|b| b.r#type == "date_histogram")
+                .unwrap();
+            assert_eq!("@timestamp", histogram.field);
+        }
+    }
 }
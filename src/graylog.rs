@@ -1,37 +1,312 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ContentPack {
     pub name: String,
+    #[serde(default)]
     pub dashboards: Vec<Dashboard>,
+    #[serde(default)]
+    pub streams: Vec<Stream>,
+    /// Content of Graylog 3.x (`"v": "1"`) content packs.
+    #[serde(default)]
+    pub entities: Vec<Entity>,
+}
+
+impl ContentPack {
+    pub fn event_definitions(&self, report: &mut Vec<String>) -> Vec<EventDefinition> {
+        self.entities_of_type("event_definition", report)
+    }
+
+    pub fn notifications(&self, report: &mut Vec<String>) -> Vec<EventNotification> {
+        self.entities_of_type("notification", report)
+    }
+
+    /// Deserializes the entities of a type, entities which don't match the model are skipped
+    /// and added to `report`.
+    fn entities_of_type<T: serde::de::DeserializeOwned>(
+        &self,
+        name: &str,
+        report: &mut Vec<String>,
+    ) -> Vec<T> {
+        self.entities
+            .iter()
+            .filter(|e| e.r#type.name == name)
+            .filter_map(|e| {
+                let mut data = Entity::resolve_values(e.data.clone());
+                data["id"] = Value::String(e.id.clone());
+                match serde_json::from_value(data) {
+                    Ok(entity) => Some(entity),
+                    Err(err) => {
+                        report.push(format!("Entity {} of type {}: {}", e.id, name, err));
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entity {
+    pub id: String,
+    pub r#type: EntityType,
+    pub data: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntityType {
+    pub name: String,
+    pub version: String,
+}
+
+impl Entity {
+    /// Content packs wrap most values as `{"@type": "string", "@value": ...}`,
+    /// this replaces them by the plain value as returned by the REST API.
+    fn resolve_values(value: Value) -> Value {
+        match value {
+            Value::Object(mut map) => {
+                if map.contains_key("@type") && map.contains_key("@value") {
+                    return Entity::resolve_values(map.remove("@value").unwrap_or_default());
+                }
+                Value::Object(
+                    map.into_iter()
+                        .map(|(k, v)| (k, Entity::resolve_values(v)))
+                        .collect(),
+                )
+            }
+            Value::Array(values) => {
+                Value::Array(values.into_iter().map(Entity::resolve_values).collect())
+            }
+            value => value,
+        }
+    }
+}
+
+/// Event definition of Graylog 3.1+, as returned by `/api/events/definitions`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventDefinition {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub priority: i64,
+    pub config: EventDefinitionConfig,
+    #[serde(default)]
+    pub notifications: Vec<EventNotificationHandler>,
+    #[serde(default)]
+    pub notification_settings: EventNotificationSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventDefinitionConfig {
+    /// `aggregation-v1` for filter and aggregation definitions.
+    pub r#type: String,
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub streams: Vec<String>,
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    #[serde(default)]
+    pub series: Vec<AggregationSeries>,
+    /// Expression tree over the series, `None` for filter-only definitions.
+    pub conditions: Option<AggregationConditions>,
+    #[serde(default)]
+    pub search_within_ms: i64,
+    #[serde(default)]
+    pub execute_every_ms: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AggregationSeries {
+    pub id: String,
+    pub function: String,
+    pub field: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AggregationConditions {
+    pub expression: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventNotificationHandler {
+    pub notification_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EventNotificationSettings {
+    #[serde(default)]
+    pub grace_period_ms: i64,
+}
+
+/// Event notification of Graylog 3.1+, as returned by `/api/events/notifications`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventNotification {
+    pub id: String,
+    pub title: String,
+    pub config: EventNotificationConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventNotificationConfig {
+    /// `email-notification-v1`, `http-notification-v1`, `slack-notification-v1`, ...
+    pub r#type: String,
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub email_recipients: Vec<String>,
+    #[serde(default)]
+    pub user_recipients: Vec<String>,
+    pub url: Option<String>,
+    pub webhook_url: Option<String>,
+    pub channel: Option<String>,
+}
+
+/// Event definitions as returned by `/api/events/definitions`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiEventDefinitions {
+    pub event_definitions: Vec<EventDefinition>,
+}
+
+/// Event notifications as returned by `/api/events/notifications`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiEventNotifications {
+    pub notifications: Vec<EventNotification>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Stream {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub alert_conditions: Vec<AlertCondition>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlertCondition {
+    pub id: String,
+    pub r#type: AlertConditionType,
+    pub title: Option<String>,
+    pub parameters: AlertConditionParameters,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AlertConditionType {
+    #[serde(rename = "message_count")]
+    MessageCount,
+    #[serde(rename = "field_value")]
+    FieldValue,
+    #[serde(rename = "field_content_value")]
+    FieldContentValue,
+    /// Alert conditions provided by plugins.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlertConditionParameters {
+    pub query: Option<String>,
+    /// Evaluation window in minutes.
+    pub time: Option<i64>,
+    pub threshold: Option<f64>,
+    /// `MORE`/`LESS` for message count, `HIGHER`/`LOWER` for field value conditions.
+    pub threshold_type: Option<String>,
+    /// Aggregation of field value conditions: `MEAN`, `MIN`, `MAX`, `SUM` or `STDDEV`.
+    pub r#type: Option<String>,
+    pub field: Option<String>,
+    pub value: Option<String>,
+    /// Minutes after an alert during which no new alert is triggered.
+    pub grace: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Dashboard {
+    /// Only known for dashboards read from the Graylog API.
+    #[serde(default)]
+    pub id: Option<String>,
     pub title: String,
     pub description: String,
     pub dashboard_widgets: Vec<DashboardWidget>,
 }
 
+/// Dashboards as returned by the Graylog REST API (`/api/dashboards`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiDashboards {
+    pub dashboards: Vec<ApiDashboard>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum DashboardWidgetType {
-    #[serde(rename = "SEARCH_RESULT_COUNT")]
-    SearchResultCount,
-    #[serde(rename = "SEARCH_RESULT_CHART")]
-    SearchResultChart,
-    #[serde(rename = "QUICKVALUES")]
-    QuickValues,
-    #[serde(rename = "FIELD_CHART")]
-    FieldChart,
-    #[serde(rename = "STACKED_CHART")]
-    StackedChart,
-    #[serde(rename = "QUICKVALUES_HISTOGRAM")]
-    QuickValuesHistogram,
+pub struct ApiDashboard {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub widgets: Vec<ApiDashboardWidget>,
+    /// Widget positions keyed by widget id, widgets which were never moved have none.
+    #[serde(default)]
+    pub positions: HashMap<String, ApiWidgetPosition>,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiDashboardWidget {
+    pub id: String,
+    pub description: String,
+    pub r#type: String,
+    pub config: DashboardWidgetConfiguration,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiWidgetPosition {
+    pub row: i64,
+    pub col: i64,
+    pub height: i64,
+    pub width: i64,
+}
+
+impl From<ApiDashboard> for Dashboard {
+    fn from(dashboard: ApiDashboard) -> Dashboard {
+        let positions = dashboard.positions;
+        Dashboard {
+            id: Some(dashboard.id),
+            title: dashboard.title,
+            description: dashboard.description,
+            dashboard_widgets: dashboard
+                .widgets
+                .into_iter()
+                .map(|widget| {
+                    let position =
+                        positions
+                            .get(&widget.id)
+                            .cloned()
+                            .unwrap_or(ApiWidgetPosition {
+                                row: 1,
+                                col: 1,
+                                height: 1,
+                                width: 1,
+                            });
+                    DashboardWidget {
+                        description: widget.description,
+                        r#type: widget.r#type,
+                        configuration: widget.config,
+                        row: position.row,
+                        col: position.col,
+                        height: position.height,
+                        width: position.width,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DashboardWidget {
     pub description: String,
-    pub r#type: DashboardWidgetType,
+    /// Widget type like `FIELD_CHART`, see `widgets::WidgetConverterRegistry`.
+    pub r#type: String,
     pub configuration: DashboardWidgetConfiguration,
     pub row: i64,
     pub col: i64,
@@ -47,10 +322,14 @@ pub struct DashboardWidgetConfiguration {
     pub renderer: Option<ChartRenderer>,
     pub field: Option<String>,
     pub series: Option<Vec<DashboardWidgetConfigStackedChartSerie>>,
+    #[serde(default)]
     pub timerange: TimeRange,
     pub trend: Option<bool>,
     pub sort_order: Option<String>,
     pub limit: Option<i64>,
+    /// Settings of widgets provided by plugins.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -70,7 +349,7 @@ pub struct DashboardWidgetConfigStackedChartSerie {
     pub statistical_function: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct TimeRange {
     pub range: i64,
 }
@@ -97,3 +376,93 @@ impl DashboardWidgetConfigSearchResultChartInterval {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn content_pack_v1_event_definitions_resolve_values() {
+        let pack: ContentPack = serde_json::from_value(json!({
+            "v": "1",
+            "name": "Events",
+            "entities": [{
+                "v": "1",
+                "type": {"name": "event_definition", "version": "1"},
+                "id": "def1",
+                "data": {
+                    "title": {"@type": "string", "@value": "Errors"},
+                    "priority": {"@type": "integer", "@value": 2},
+                    "config": {
+                        "type": {"@type": "string", "@value": "aggregation-v1"},
+                        "query": {"@type": "string", "@value": "level:3"},
+                        "streams": [{"@type": "string", "@value": "s1"}],
+                        "series": [{"id": "count-", "function": "count", "field": null}],
+                        "conditions": {"expression": null},
+                        "search_within_ms": {"@type": "long", "@value": 300000},
+                        "execute_every_ms": {"@type": "long", "@value": 60000}
+                    },
+                    "notifications": [{"notification_id": {"@type": "string", "@value": "n1"}}]
+                }
+            }, {
+                "v": "1",
+                "type": {"name": "dashboard", "version": "2"},
+                "id": "d1",
+                "data": {}
+            }]
+        }))
+        .unwrap();
+
+        let mut report = vec![];
+        let definitions = pack.event_definitions(&mut report);
+
+        assert!(report.is_empty());
+        assert_eq!(1, definitions.len());
+        assert_eq!("def1", definitions[0].id);
+        assert_eq!("Errors", definitions[0].title);
+        assert_eq!(vec!["s1".to_string()], definitions[0].config.streams);
+        assert_eq!(300000, definitions[0].config.search_within_ms);
+        assert_eq!("n1", definitions[0].notifications[0].notification_id);
+        assert!(pack.dashboards.is_empty());
+    }
+
+    #[test]
+    fn api_dashboard_into_dashboard_uses_widget_positions() {
+        let api: ApiDashboard = serde_json::from_str(
+            r#"{
+                "id": "5d1",
+                "title": "Errors",
+                "widgets": [
+                    {"id": "w1", "description": "moved", "type": "SEARCH_RESULT_COUNT",
+                     "config": {"query": "level:3", "timerange": {"type": "relative", "range": 300}}},
+                    {"id": "w2", "description": "new", "type": "SEARCH_RESULT_COUNT",
+                     "config": {"query": "", "timerange": {"type": "relative", "range": 300}}}
+                ],
+                "positions": {"w1": {"row": 2, "col": 3, "height": 1, "width": 2}}
+            }"#,
+        )
+        .unwrap();
+
+        let dashboard = Dashboard::from(api);
+
+        assert_eq!(Some("5d1".to_string()), dashboard.id);
+        assert_eq!("", dashboard.description);
+        assert_eq!(
+            (2, 3, 2),
+            (
+                dashboard.dashboard_widgets[0].row,
+                dashboard.dashboard_widgets[0].col,
+                dashboard.dashboard_widgets[0].width
+            )
+        );
+        assert_eq!(
+            (1, 1, 1),
+            (
+                dashboard.dashboard_widgets[1].row,
+                dashboard.dashboard_widgets[1].col,
+                dashboard.dashboard_widgets[1].width
+            )
+        );
+    }
+}
@@ -0,0 +1,438 @@
+//! Intermediate representation between the Graylog input and the output formats.
+//!
+//! Input parsers turn dashboards into the types of this module, resolving the quirks of
+//! their source, backends render them without knowing where they came from. The types
+//! serialize to JSON, `graylog-to-grafana dump-ir` shows what a content pack parses to.
+
+use crate::graylog;
+use crate::widgets::WidgetConverterRegistry;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Dashboards read together, e.g. from one content pack.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContentPack {
+    pub name: String,
+    pub dashboards: Vec<Dashboard>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Dashboard {
+    /// Id in the source system, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub panels: Vec<Panel>,
+    /// Widgets of the source which have no panel.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<SkippedWidget>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SkippedWidget {
+    pub title: String,
+    pub source_type: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Panel {
+    pub title: String,
+    pub visualization: Visualization,
+    pub queries: Vec<Query>,
+    /// Seconds before now the panel shows, `None` to follow the dashboard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_range: Option<i64>,
+    #[serde(default)]
+    pub layout: Layout,
+    /// Type of the widget the panel was parsed from, e.g. `FIELD_CHART`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_type: Option<String>,
+    /// Where the panel deviates from the widget.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+}
+
+/// Setting of a widget which is approximated or dropped.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// A statistical function replaced by a different one.
+    ApproximatedMetric,
+    /// A time range replaced or rounded.
+    TimeRange,
+    /// A position outside of the target grid.
+    LayoutOverflow,
+    /// A query which isn't valid Lucene syntax.
+    UnparseableQuery,
+}
+
+/// How the results of the queries are shown.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Visualization {
+    /// A single number, with its course over time if `trend` is set.
+    SingleValue { trend: bool },
+    /// Values over time.
+    Chart { style: ChartStyle },
+    /// Share of each bucket in the total.
+    Pie,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChartStyle {
+    Bar,
+    Line,
+    Area,
+}
+
+/// Search for messages, aggregated into a metric per bucket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Query {
+    /// Lucene query, `*` for all messages.
+    pub query: String,
+    pub metric: Metric,
+    /// Nested bucket aggregations, outermost first.
+    #[serde(default)]
+    pub buckets: Vec<Bucket>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub function: MetricFunction,
+    /// Message field the function is applied to, `None` when counting messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Cardinality,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Bucket {
+    /// Fixed time intervals like `1m` or `7d`.
+    DateHistogram { interval: String },
+    /// The `size` most frequent values of `field` in `order`, `asc` or `desc`.
+    Terms {
+        field: String,
+        order: String,
+        size: i64,
+    },
+}
+
+/// Position on the grid of the source dashboard, rows and columns counted from 1.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub row: i64,
+    pub col: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            row: 1,
+            col: 1,
+            width: 1,
+            height: 1,
+        }
+    }
+}
+
+impl ContentPack {
+    /// Parses the dashboards of a Graylog content pack, converting the widgets with
+    /// the converter registered for their type.
+    pub fn from_graylog(
+        content_pack: graylog::ContentPack,
+        widgets: &WidgetConverterRegistry,
+    ) -> Result<ContentPack, Box<dyn Error>> {
+        Ok(ContentPack {
+            name: content_pack.name,
+            dashboards: content_pack
+                .dashboards
+                .into_iter()
+                .map(|d| Dashboard::from_graylog(d, widgets))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl Dashboard {
+    pub fn new(title: &str) -> Dashboard {
+        Dashboard {
+            id: None,
+            title: title.to_string(),
+            description: String::new(),
+            panels: vec![],
+            skipped: vec![],
+        }
+    }
+
+    pub fn with_panel(mut self, panel: Panel) -> Dashboard {
+        self.panels.push(panel);
+        self
+    }
+
+    pub fn from_graylog(
+        dashboard: graylog::Dashboard,
+        widgets: &WidgetConverterRegistry,
+    ) -> Result<Dashboard, Box<dyn Error>> {
+        let mut panels = vec![];
+        let mut skipped = vec![];
+        for widget in dashboard.dashboard_widgets {
+            let title = widget.description.clone();
+            let source_type = widget.r#type.clone();
+            match widgets.convert(widget)? {
+                Some(panel) => panels.push(check_queries(panel)),
+                None => skipped.push(SkippedWidget {
+                    reason: if widgets.is_registered(&source_type) {
+                        format!("Left out by the {} converter", source_type)
+                    } else {
+                        format!("No converter for {} widgets", source_type)
+                    },
+                    title,
+                    source_type,
+                }),
+            }
+        }
+        Ok(Dashboard {
+            id: dashboard.id,
+            title: dashboard.title,
+            description: dashboard.description,
+            panels,
+            skipped,
+        })
+    }
+}
+
+/// Warns about queries with syntax errors, which fail when searching.
+fn check_queries(panel: Panel) -> Panel {
+    let errors: Vec<String> = panel
+        .queries
+        .iter()
+        .filter_map(|q| syntax_error(&q.query).map(|e| format!("query \"{}\" {}", q.query, e)))
+        .collect();
+    errors.iter().fold(panel, |panel, error| {
+        panel.with_warning(WarningKind::UnparseableQuery, error)
+    })
+}
+
+/// Obvious Lucene syntax errors: unbalanced parentheses, ranges or quotes and dangling operators.
+pub fn syntax_error(query: &str) -> Option<&'static str> {
+    let mut groups = vec![];
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in query.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '(' => groups.push(')'),
+            '[' | '{' => groups.push(']'),
+            ')' if groups.pop() != Some(')') => return Some("has unbalanced parentheses"),
+            ']' | '}' if groups.pop() != Some(']') => return Some("has an unbalanced range"),
+            _ => {}
+        }
+    }
+    if quoted {
+        return Some("has an unterminated quote");
+    }
+    match groups.last() {
+        Some(')') => return Some("has unbalanced parentheses"),
+        Some(_) => return Some("has an unbalanced range"),
+        None => {}
+    }
+    let binary = |word: &str| matches!(word, "AND" | "OR" | "&&" | "||");
+    let first = query.split_whitespace().next().unwrap_or_default();
+    let last = query.split_whitespace().last().unwrap_or_default();
+    if binary(first) || binary(last) || last == "NOT" {
+        return Some("has a dangling operator");
+    }
+    None
+}
+
+impl Panel {
+    pub fn new(title: &str, visualization: Visualization) -> Panel {
+        Panel {
+            title: title.to_string(),
+            visualization,
+            queries: vec![],
+            time_range: None,
+            layout: Layout::default(),
+            source_type: None,
+            warnings: vec![],
+        }
+    }
+
+    pub fn with_query(mut self, query: Query) -> Panel {
+        self.queries.push(query);
+        self
+    }
+
+    pub fn with_time_range(mut self, seconds: i64) -> Panel {
+        self.time_range = Some(seconds);
+        self
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Panel {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_warning(mut self, kind: WarningKind, message: &str) -> Panel {
+        self.warnings.push(Warning {
+            kind,
+            message: message.to_string(),
+        });
+        self
+    }
+}
+
+impl Query {
+    /// Counts the messages matching `query`.
+    pub fn new_count(query: &str) -> Query {
+        Query {
+            query: query.to_string(),
+            metric: Metric {
+                function: MetricFunction::Count,
+                field: None,
+            },
+            buckets: vec![],
+        }
+    }
+
+    pub fn with_metric(mut self, function: MetricFunction, field: Option<String>) -> Query {
+        self.metric = Metric { function, field };
+        self
+    }
+
+    pub fn with_bucket(mut self, bucket: Bucket) -> Query {
+        self.buckets.push(bucket);
+        self
+    }
+}
+
+impl Layout {
+    pub fn new_with_widget(widget: &graylog::DashboardWidget) -> Layout {
+        Layout {
+            row: widget.row,
+            col: widget.col,
+            width: widget.width,
+            height: widget.height,
+        }
+    }
+}
+
+impl MetricFunction {
+    /// Name of the Elasticsearch aggregation computing the function.
+    pub fn aggregation(self) -> &'static str {
+        match self {
+            MetricFunction::Count => "count",
+            MetricFunction::Sum => "sum",
+            MetricFunction::Avg => "avg",
+            MetricFunction::Min => "min",
+            MetricFunction::Max => "max",
+            MetricFunction::Cardinality => "cardinality",
+        }
+    }
+}
+
+/// Widgets with conversion issues for the tests of the modules dealing with them.
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+
+    /// Widget without a converter.
+    pub fn skipped_map(title: &str) -> SkippedWidget {
+        SkippedWidget {
+            title: title.to_string(),
+            source_type: "ORG_MAP".to_string(),
+            reason: "No converter for ORG_MAP widgets".to_string(),
+        }
+    }
+
+    /// Panel with an approximated statistical function.
+    pub fn approximated_panel(title: &str, visualization: Visualization) -> Panel {
+        Panel::new(title, visualization)
+            .with_query(Query::new_count("*"))
+            .with_warning(
+                WarningKind::ApproximatedMetric,
+                "std_deviation approximated as sum",
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_tagged_visualizations_and_buckets() {
+        let panel = Panel::new("Levels", Visualization::Pie)
+            .with_query(Query::new_count("source:web").with_bucket(Bucket::Terms {
+                field: "level".to_string(),
+                order: "desc".to_string(),
+                size: 5,
+            }))
+            .with_time_range(300);
+
+        let json = serde_json::to_value(&panel).unwrap();
+
+        assert_eq!(
+            json!({
+                "title": "Levels",
+                "visualization": {"kind": "pie"},
+                "queries": [{
+                    "query": "source:web",
+                    "metric": {"function": "count"},
+                    "buckets": [{"type": "terms", "field": "level", "order": "desc", "size": 5}],
+                }],
+                "time_range": 300,
+                "layout": {"row": 1, "col": 1, "width": 1, "height": 1},
+            }),
+            json
+        );
+        assert_eq!(panel, serde_json::from_value(json).unwrap());
+    }
+
+    #[test]
+    fn syntax_errors_in_queries() {
+        assert_eq!(
+            None,
+            syntax_error("level:3 AND (source:web OR source:\"db (1)\")")
+        );
+        assert_eq!(None, syntax_error("NOT took_ms:[100 TO *} AND path:\\("));
+        assert_eq!(Some("has unbalanced parentheses"), syntax_error("(level:3"));
+        assert_eq!(
+            Some("has an unbalanced range"),
+            syntax_error("took_ms:[1 TO 5")
+        );
+        assert_eq!(
+            Some("has an unterminated quote"),
+            syntax_error("message:\"failed")
+        );
+        assert_eq!(Some("has a dangling operator"), syntax_error("level:3 AND"));
+        assert_eq!(Some("has a dangling operator"), syntax_error("OR level:3"));
+    }
+}
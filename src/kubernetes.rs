@@ -0,0 +1,269 @@
+use crate::grafana::Dashboard;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// File receiving all resources if they are written into a single file.
+pub const RESOURCES_FILE: &str = "grafana-dashboards.yaml";
+
+/// Kind of resource wrapping a dashboard.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceKind {
+    /// `GrafanaDashboard` custom resource of the grafana-operator.
+    GrafanaDashboard,
+    /// ConfigMap picked up by the dashboard sidecar of the Grafana helm chart.
+    ConfigMap,
+}
+
+impl FromStr for ResourceKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<ResourceKind, String> {
+        match kind {
+            "grafana-dashboard" => Ok(ResourceKind::GrafanaDashboard),
+            "config-map" => Ok(ResourceKind::ConfigMap),
+            _ => Err(format!("Unknown resource kind {}", kind)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KubernetesOptions {
+    pub kind: ResourceKind,
+    pub namespace: Option<String>,
+    /// Labels selecting the Grafana instances of the operator which receive the dashboards.
+    pub instance_selector: BTreeMap<String, String>,
+    /// Annotation the sidecar reads the folder from, `folderAnnotation` in the helm chart.
+    pub folder_annotation: String,
+}
+
+impl Default for KubernetesOptions {
+    fn default() -> KubernetesOptions {
+        let mut instance_selector = BTreeMap::new();
+        instance_selector.insert("dashboards".to_string(), "grafana".to_string());
+        KubernetesOptions {
+            kind: ResourceKind::GrafanaDashboard,
+            namespace: None,
+            instance_selector,
+            folder_annotation: "grafana_folder".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Resource {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: Metadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spec: Option<GrafanaDashboardSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Metadata {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GrafanaDashboardSpec {
+    #[serde(rename = "instanceSelector")]
+    pub instance_selector: LabelSelector,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    pub json: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LabelSelector {
+    #[serde(rename = "matchLabels")]
+    pub match_labels: BTreeMap<String, String>,
+}
+
+impl Resource {
+    pub fn new(dashboard: &Dashboard, opt: &KubernetesOptions) -> Result<Resource, Box<dyn Error>> {
+        let json = serde_json::to_string(dashboard)?;
+        let mut metadata = Metadata {
+            name: resource_name(&dashboard.uid),
+            namespace: opt.namespace.clone(),
+            labels: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+        };
+        Ok(match opt.kind {
+            ResourceKind::GrafanaDashboard => Resource {
+                api_version: "grafana.integreatly.org/v1beta1".to_string(),
+                kind: "GrafanaDashboard".to_string(),
+                metadata,
+                spec: Some(GrafanaDashboardSpec {
+                    instance_selector: LabelSelector {
+                        match_labels: opt.instance_selector.clone(),
+                    },
+                    folder: dashboard.folder_title.clone(),
+                    json,
+                }),
+                data: None,
+            },
+            ResourceKind::ConfigMap => {
+                metadata
+                    .labels
+                    .insert("grafana_dashboard".to_string(), "1".to_string());
+                if let Some(folder) = &dashboard.folder_title {
+                    metadata
+                        .annotations
+                        .insert(opt.folder_annotation.clone(), folder.clone());
+                }
+                let mut data = BTreeMap::new();
+                data.insert(config_map_key(&dashboard.file_name()), json);
+                Resource {
+                    api_version: "v1".to_string(),
+                    kind: "ConfigMap".to_string(),
+                    metadata,
+                    spec: None,
+                    data: Some(data),
+                }
+            }
+        })
+    }
+}
+
+/// Writes a resource per dashboard into `dir`, either into a file each or all as documents
+/// of `RESOURCES_FILE`.
+pub fn write(
+    dir: &Path,
+    dashboards: &[Dashboard],
+    opt: &KubernetesOptions,
+    single_file: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut all = vec![];
+    let mut names = BTreeSet::new();
+    for dashboard in dashboards {
+        let mut resource = Resource::new(dashboard, opt)?;
+        resource.metadata.name = unique_name(&resource.metadata.name, |name| names.contains(name));
+        names.insert(resource.metadata.name.clone());
+        let yaml = serde_yaml::to_string(&resource)?;
+        if single_file {
+            all.push(yaml);
+        } else {
+            let path = dir.join(format!("{}.yaml", resource.metadata.name));
+            writeln!(File::create(path)?, "{}", yaml)?;
+        }
+    }
+    if single_file {
+        writeln!(
+            File::create(dir.join(RESOURCES_FILE))?,
+            "{}",
+            all.join("\n")
+        )?;
+    }
+    Ok(())
+}
+
+/// Kubernetes names allow lower case letters, digits and inner dashes, up to 63 characters
+/// for labels.
+fn resource_name(uid: &str) -> String {
+    let mut name = String::new();
+    for c in uid.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    name.truncate(63);
+    let name = name.trim_end_matches('-');
+    if name.is_empty() {
+        "dashboard".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Appends a counter to names which are already taken, shortening the name to stay a valid
+/// label.
+fn unique_name<F: Fn(&str) -> bool>(name: &str, taken: F) -> String {
+    if !taken(name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|i| {
+            let suffix = format!("-{}", i);
+            let mut base = name.to_string();
+            base.truncate(63 - suffix.len());
+            format!("{}{}", base.trim_end_matches('-'), suffix)
+        })
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+/// ConfigMap keys allow letters, digits, `-`, `_` and `.` only.
+fn config_map_key(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_name_is_dns_label() {
+        assert_eq!("web-errors-5xx", resource_name("--web--errors--5xx-"));
+        assert_eq!("dashboard", resource_name("---"));
+    }
+
+    #[test]
+    fn unique_names_stay_labels() {
+        let long = "a".repeat(63);
+        assert_eq!("web", unique_name("web", |_| false));
+        assert_eq!("web-3", unique_name("web", |name| name != "web-3"));
+        assert_eq!(
+            format!("{}-2", "a".repeat(61)),
+            unique_name(&long, |name| name == long)
+        );
+    }
+
+    #[test]
+    fn config_map_key_is_sanitized() {
+        assert_eq!("Fehler__bersicht_", config_map_key("Fehler_Übersicht:"));
+        assert_eq!("web-errors_5xx.json", config_map_key("web-errors_5xx.json"));
+    }
+
+    #[test]
+    fn config_map_with_folder_annotation() {
+        let dashboard = Dashboard::new("Web Errors").with_folder_title("Graylog");
+        let opt = KubernetesOptions {
+            kind: ResourceKind::ConfigMap,
+            namespace: Some("monitoring".to_string()),
+            ..KubernetesOptions::default()
+        };
+
+        let resource = Resource::new(&dashboard, &opt).unwrap();
+
+        assert_eq!("web-errors-90fa04f0", resource.metadata.name);
+        assert_eq!("1", resource.metadata.labels["grafana_dashboard"]);
+        assert_eq!("Graylog", resource.metadata.annotations["grafana_folder"]);
+        assert!(
+            resource.data.unwrap()["web_errors.json"].contains("\"uid\":\"web-errors-90fa04f0\"")
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Converts Graylog dashboards, alert conditions and event definitions into their
+//! Grafana counterparts.
+//!
+//! The command line tool `graylog-to-grafana` is a thin layer over this crate, other
+//! tools can use [`convert`] and the typed Grafana model in [`grafana`] directly.
+//!
+//! Dashboards are parsed into the intermediate representation in [`ir`] first, which
+//! the Grafana model, the Perses dashboards and the Kibana saved objects are built from.
+
+use std::error::Error;
+
+pub mod alerting;
+pub mod backup;
+pub mod client;
+pub mod config;
+pub mod credentials;
+pub mod dashboard_v2;
+pub mod diff;
+pub mod export;
+pub mod grafana;
+pub mod graylog;
+pub mod ir;
+pub mod kubernetes;
+pub mod perses;
+pub mod policy;
+pub mod provisioning;
+pub mod report;
+pub mod reverse;
+pub mod saved_objects;
+pub mod state;
+pub mod sync;
+pub mod terraform;
+pub mod upload;
+pub mod widgets;
+
+/// Settings of a conversion, independent of the command line.
+#[derive(Debug, Clone)]
+pub struct ConversionOptions {
+    /// Name of the Elasticsearch datasource all panels query.
+    pub datasource: String,
+    /// Graylog url, panels link to the matching Graylog search if set.
+    pub graylog_url: Option<String>,
+    /// Datasource for the Graylog events index, adds event annotations to every dashboard if set.
+    pub events_datasource: Option<String>,
+    /// Query selecting the events shown as annotations.
+    pub events_query: String,
+    /// Elasticsearch field holding the message time.
+    pub time_field: String,
+    /// Message fields shown by the "Go to Graylog" links.
+    pub link_fields: String,
+    /// Settings for all dashboards.
+    pub defaults: config::DashboardSettings,
+    /// Settings for single dashboards, applied in order on top of `defaults`.
+    pub dashboards: Vec<config::DashboardOverride>,
+    /// Settings winning over all others, e.g. given on the command line.
+    pub overrides: config::DashboardSettings,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> ConversionOptions {
+        ConversionOptions {
+            datasource: "graylog".to_string(),
+            graylog_url: None,
+            events_datasource: None,
+            events_query: "*".to_string(),
+            time_field: "timestamp".to_string(),
+            link_fields: "message,source".to_string(),
+            defaults: config::DashboardSettings::default(),
+            dashboards: vec![],
+            overrides: config::DashboardSettings::default(),
+        }
+    }
+}
+
+impl ConversionOptions {
+    /// Settings for `dashboard` from all levels.
+    pub fn settings_for(&self, dashboard: &ir::Dashboard) -> config::DashboardSettings {
+        self.dashboards
+            .iter()
+            .filter(|d| d.matches(dashboard))
+            .fold(self.defaults.clone(), |settings, d| {
+                settings.merge(&d.settings)
+            })
+            .merge(&self.overrides)
+    }
+}
+
+/// Converts all dashboards of a Graylog content pack into Grafana dashboards.
+///
+/// Widgets without a Grafana counterpart are left out with a warning, widgets missing
+/// required configuration fail the conversion.
+///
+/// ```no_run
+/// use graylog_to_grafana::{convert, ConversionOptions};
+///
+/// let file = std::fs::File::open("content_pack.json")?;
+/// let content_pack = serde_json::from_reader(file)?;
+/// let options = ConversionOptions {
+///     graylog_url: Some("https://graylog.example.com".to_string()),
+///     ..ConversionOptions::default()
+/// };
+/// for dashboard in convert(content_pack, &options)? {
+///     println!("{}", serde_json::to_string_pretty(&dashboard)?);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn convert(
+    content_pack: graylog::ContentPack,
+    options: &ConversionOptions,
+) -> Result<Vec<grafana::Dashboard>, Box<dyn Error>> {
+    convert_with(
+        content_pack,
+        options,
+        &widgets::WidgetConverterRegistry::default(),
+    )
+}
+
+/// Like [`convert`], with converters for additional widget types, e.g. from Graylog plugins.
+///
+/// ```no_run
+/// use graylog_to_grafana::ir::{ChartStyle, Panel, Query, Visualization};
+/// use graylog_to_grafana::widgets::WidgetConverterRegistry;
+/// use graylog_to_grafana::{convert_with, graylog, ConversionOptions};
+///
+/// let mut widgets = WidgetConverterRegistry::default();
+/// widgets.register("ORG_HEATMAP", |widget: graylog::DashboardWidget| {
+///     let style = ChartStyle::Bar;
+///     let query = widget.configuration.query.as_deref().unwrap_or("*");
+///     Ok(Some(
+///         Panel::new(&widget.description, Visualization::Chart { style })
+///             .with_query(Query::new_count(query)),
+///     ))
+/// });
+/// let content_pack = serde_json::from_reader(std::fs::File::open("content_pack.json")?)?;
+/// let dashboards = convert_with(content_pack, &ConversionOptions::default(), &widgets)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn convert_with(
+    content_pack: graylog::ContentPack,
+    options: &ConversionOptions,
+    widgets: &widgets::WidgetConverterRegistry,
+) -> Result<Vec<grafana::Dashboard>, Box<dyn Error>> {
+    convert_ir(
+        &ir::ContentPack::from_graylog(content_pack, widgets)?,
+        options,
+    )
+}
+
+/// Parses a Graylog content pack into the intermediate representation with the
+/// built-in widget converters.
+pub fn parse(content_pack: graylog::ContentPack) -> Result<ir::ContentPack, Box<dyn Error>> {
+    ir::ContentPack::from_graylog(content_pack, &widgets::WidgetConverterRegistry::default())
+}
+
+/// Converts dashboards of the intermediate representation into Grafana dashboards.
+pub fn convert_ir(
+    content_pack: &ir::ContentPack,
+    options: &ConversionOptions,
+) -> Result<Vec<grafana::Dashboard>, Box<dyn Error>> {
+    content_pack
+        .dashboards
+        .iter()
+        .map(|d| grafana::Dashboard::from_ir(d, &content_pack.name, options))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_for_dashboard() {
+        let options = ConversionOptions {
+            defaults: config::DashboardSettings {
+                datasource: Some("elasticsearch".to_string()),
+                folder: Some(1),
+                ..config::DashboardSettings::default()
+            },
+            dashboards: vec![config::DashboardOverride {
+                id: Some("5d1".to_string()),
+                settings: config::DashboardSettings {
+                    datasource: Some("web-logs".to_string()),
+                    folder: Some(2),
+                    ..config::DashboardSettings::default()
+                },
+                ..config::DashboardOverride::default()
+            }],
+            overrides: config::DashboardSettings {
+                folder: Some(3),
+                ..config::DashboardSettings::default()
+            },
+            ..ConversionOptions::default()
+        };
+        let dashboard = |id: &str| ir::Dashboard {
+            id: Some(id.to_string()),
+            ..ir::Dashboard::new("Web")
+        };
+
+        let web = options.settings_for(&dashboard("5d1"));
+        let other = options.settings_for(&dashboard("5d2"));
+
+        assert_eq!(Some("web-logs".to_string()), web.datasource);
+        assert_eq!(Some("elasticsearch".to_string()), other.datasource);
+        assert_eq!((Some(3), Some(3)), (web.folder, other.folder));
+    }
+}
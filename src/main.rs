@@ -1,13 +1,30 @@
-use reqwest::Client;
+use graylog_to_grafana::client::ConnectionOptions;
+use graylog_to_grafana::config::{Config, DashboardSettings};
+use graylog_to_grafana::credentials::{CredentialSources, Secret};
+use graylog_to_grafana::policy::FailurePolicy;
+use graylog_to_grafana::{
+    alerting, backup, client, convert_ir, dashboard_v2, diff, export, grafana, graylog, kubernetes,
+    parse, perses, policy, provisioning, report, reverse, saved_objects, state, sync, terraform,
+    upload, ConversionOptions,
+};
+use log::{error, info, warn};
+use serde::Serialize;
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::clap;
 use structopt::StructOpt;
 
-mod grafana;
-mod graylog;
+/// Exit code when reading, writing or talking to Grafana or Graylog failed
+const EXIT_ERROR: i32 = 2;
+/// Exit code when the failure policy left out dashboards, the others were converted
+const EXIT_DASHBOARDS_FAILED: i32 = 3;
+/// Exit code when the failure policy failed the run, no dashboards were converted
+const EXIT_RUN_FAILED: i32 = 4;
 
 /// Allows to save Grafana dashboards into a directory
 #[derive(StructOpt, Debug)]
@@ -15,18 +32,466 @@ pub struct GenerateArguments {
     /// Directory for output grafana dashboards
     #[structopt(name = "output", parse(from_os_str))]
     output: PathBuf,
+
+    /// Output format, `export` replaces datasources by inputs like Grafana's export for sharing,
+    /// `v2` writes dashboard resources of the v2 schema, `provisioning` adds a provider config and folder directories,
+    /// `kubernetes` wraps dashboards into resources, `terraform` adds resources of the Grafana provider,
+    /// `perses` writes Perses dashboards with placeholder queries, `saved-objects` Kibana and OpenSearch Dashboards objects
+    #[structopt(
+        long = "format",
+        default_value = "json",
+        raw(
+            possible_values = r#"&["json", "export", "v2", "provisioning", "kubernetes", "terraform", "perses", "saved-objects"]"#
+        )
+    )]
+    format: OutputFormat,
+
+    /// Title of the folder for provisioning, Kubernetes and Terraform, overrides the config file
+    #[structopt(long = "folder-title")]
+    folder_title: Option<String>,
+
+    /// Directory Grafana reads the provisioned dashboards from
+    #[structopt(
+        long = "provisioning-path",
+        default_value = "/var/lib/grafana/dashboards"
+    )]
+    provisioning_path: String,
+
+    /// Mark provisioned dashboards with a `__provenance` key naming tool and content pack
+    #[structopt(long = "provenance")]
+    provenance: bool,
+
+    #[structopt(flatten)]
+    kubernetes: KubernetesArguments,
+
+    /// Syntax of the Terraform configuration
+    #[structopt(
+        long = "terraform-syntax",
+        default_value = "hcl",
+        raw(possible_values = r#"&["hcl", "json"]"#)
+    )]
+    terraform_syntax: terraform::Syntax,
+
+    /// Perses project of the dashboards
+    #[structopt(long = "perses-project", default_value = "graylog")]
+    perses_project: String,
+
+    /// Syntax of the Perses dashboards
+    #[structopt(
+        long = "perses-syntax",
+        default_value = "yaml",
+        raw(possible_values = r#"&["json", "yaml"]"#)
+    )]
+    perses_syntax: perses::Syntax,
+
+    /// Index pattern of the Graylog indices for saved objects
+    #[structopt(long = "index-pattern", default_value = "graylog_*")]
+    index_pattern: String,
+}
+
+/// Resources wrapping the dashboards for `--format kubernetes`
+#[derive(StructOpt, Debug)]
+pub struct KubernetesArguments {
+    /// `GrafanaDashboard` resources of the grafana-operator or ConfigMaps for the dashboard sidecar
+    #[structopt(
+        long = "kubernetes-kind",
+        default_value = "grafana-dashboard",
+        raw(possible_values = r#"&["grafana-dashboard", "config-map"]"#)
+    )]
+    kind: kubernetes::ResourceKind,
+
+    /// Namespace of the resources
+    #[structopt(long = "namespace")]
+    namespace: Option<String>,
+
+    /// Label `key=value` selecting the Grafana instances of the operator [default: dashboards=grafana]
+    #[structopt(long = "instance-selector", raw(number_of_values = "1"))]
+    instance_selector: Vec<String>,
+
+    /// Annotation the sidecar reads the folder from
+    #[structopt(long = "folder-annotation", default_value = "grafana_folder")]
+    folder_annotation: String,
+
+    /// Write all resources as documents of a single file
+    #[structopt(long = "single-file")]
+    single_file: bool,
+}
+
+impl KubernetesArguments {
+    fn options(&self) -> Result<kubernetes::KubernetesOptions, Box<dyn Error>> {
+        let mut opt = kubernetes::KubernetesOptions {
+            kind: self.kind,
+            namespace: self.namespace.clone(),
+            folder_annotation: self.folder_annotation.clone(),
+            ..kubernetes::KubernetesOptions::default()
+        };
+        if !self.instance_selector.is_empty() {
+            opt.instance_selector.clear();
+            for label in &self.instance_selector {
+                match label.splitn(2, '=').collect::<Vec<_>>()[..] {
+                    [key, value] => {
+                        opt.instance_selector
+                            .insert(key.to_string(), value.to_string());
+                    }
+                    _ => return Err(format!("Label {} is no key=value pair", label).into()),
+                }
+            }
+        }
+        Ok(opt)
+    }
+}
+
+#[derive(Debug)]
+pub enum OutputFormat {
+    Json,
+    Export,
+    V2,
+    Provisioning,
+    Kubernetes,
+    Terraform,
+    Perses,
+    SavedObjects,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<OutputFormat, String> {
+        match format {
+            "json" => Ok(OutputFormat::Json),
+            "export" => Ok(OutputFormat::Export),
+            "v2" => Ok(OutputFormat::V2),
+            "provisioning" => Ok(OutputFormat::Provisioning),
+            "kubernetes" => Ok(OutputFormat::Kubernetes),
+            "terraform" => Ok(OutputFormat::Terraform),
+            "perses" => Ok(OutputFormat::Perses),
+            "saved-objects" => Ok(OutputFormat::SavedObjects),
+            _ => Err(format!("Unknown output format {}", format)),
+        }
+    }
+}
+/// Grafana instance to talk to
+#[derive(StructOpt, Debug)]
+pub struct GrafanaArguments {
+    #[structopt(long = "url")]
+    url: Option<String>,
+
+    /// Service account token or API key, prefer the environment variable or --token-file
+    #[structopt(long = "token", env = "GRAFANA_TOKEN", raw(hide_env_values = "true"))]
+    token: Option<Secret>,
+
+    /// File containing the service account token or API key
+    #[structopt(long = "token-file", parse(from_os_str))]
+    token_file: Option<PathBuf>,
+
+    /// User for basic auth instead of a token
+    #[structopt(long = "user", env = "GRAFANA_USER")]
+    user: Option<String>,
+
+    /// Password for basic auth, prefer the environment variable or --password-file
+    #[structopt(
+        long = "password",
+        env = "GRAFANA_PASSWORD",
+        raw(hide_env_values = "true")
+    )]
+    password: Option<Secret>,
+
+    /// File containing the password for basic auth
+    #[structopt(long = "password-file", parse(from_os_str))]
+    password_file: Option<PathBuf>,
+}
+
+impl GrafanaArguments {
+    fn credential_sources(&self) -> CredentialSources {
+        CredentialSources {
+            token: self.token.clone(),
+            token_file: self.token_file.clone(),
+            user: self.user.clone(),
+            password: self.password.clone(),
+            password_file: self.password_file.clone(),
+        }
+    }
+
+    fn client(&self, connection: &ConnectionOptions) -> client::GrafanaClient {
+        let url = self
+            .url
+            .as_ref()
+            .unwrap_or_else(|| missing_argument("--url"));
+        let credentials = self
+            .credential_sources()
+            .credentials()
+            .unwrap_or_else(|e| invalid_credentials(&*e));
+        client::GrafanaClient::new(url, credentials, connection)
+            .unwrap_or_else(|e| invalid_connection(&*e))
+    }
 }
 /// Allows to add automatically dashboards to Grafana
 #[derive(StructOpt, Debug)]
 pub struct AddArguments {
-    #[structopt(long = "url")]
-    url: String,
+    #[structopt(flatten)]
+    grafana: GrafanaArguments,
+
+    /// Id of the Grafana folder, overrides the config file [default: 0]
+    #[structopt(long = "folder")]
+    folder: Option<i64>,
+
+    /// Only print what would change in Grafana, without writing anything
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+
+    #[structopt(flatten)]
+    backup: BackupArguments,
+
+    /// Delete dashboards of this content pack in the folder which were not part of this run
+    #[structopt(long = "prune")]
+    prune: bool,
+
+    /// Upload through `/api/dashboards/import` in the format for sharing
+    #[structopt(long = "import")]
+    import: bool,
+
+    /// `legacy` dashboard API, `v2` resource API of Grafana 12+, or `auto` to use v2 if supported [default: legacy]
+    #[structopt(
+        long = "dashboard-api",
+        raw(
+            possible_values = r#"&["legacy", "v2", "auto"]"#,
+            conflicts_with = r#""import""#
+        )
+    )]
+    dashboard_api: Option<String>,
+
+    /// Namespace of the organisation for the v2 resource API
+    #[structopt(long = "api-namespace", default_value = "default")]
+    api_namespace: String,
+
+    /// Number of dashboards uploaded at the same time
+    #[structopt(long = "concurrency", default_value = "4")]
+    concurrency: usize,
+}
+/// Backups of dashboards which get overwritten
+#[derive(StructOpt, Debug)]
+pub struct BackupArguments {
+    /// Directory for backups of dashboards which get overwritten
+    #[structopt(long = "backup-dir", default_value = "backups", parse(from_os_str))]
+    backup_dir: PathBuf,
+
+    /// Overwrite existing dashboards without backing them up
+    #[structopt(long = "no-backup")]
+    no_backup: bool,
+}
+
+impl AddArguments {
+    /// API to upload through, `auto` asks Grafana for its version and whether it serves
+    /// the v2 API.
+    fn upload_api(
+        &self,
+        client: &client::GrafanaClient,
+    ) -> Result<upload::UploadApi, Box<dyn Error>> {
+        let v2 = match self.dashboard_api.as_deref() {
+            Some("v2") => true,
+            Some("auto") => {
+                let version = client.get_version()?;
+                info!("Grafana {} detected", version);
+                if !dashboard_v2::supports_v2(&version) {
+                    false
+                } else if client.serves_api(dashboard_v2::API_VERSION)? {
+                    true
+                } else {
+                    warn!(
+                        "Grafana doesn't serve {}, falling back to the dashboards API",
+                        dashboard_v2::API_VERSION
+                    );
+                    false
+                }
+            }
+            _ => false,
+        };
+        Ok(if self.import {
+            upload::UploadApi::Import
+        } else if v2 {
+            upload::UploadApi::ResourceV2 {
+                namespace: self.api_namespace.clone(),
+            }
+        } else {
+            upload::UploadApi::Dashboards
+        })
+    }
+}
+
+impl BackupArguments {
+    fn backup(&self) -> Option<backup::Backup> {
+        if self.no_backup {
+            None
+        } else {
+            Some(backup::Backup::new(&self.backup_dir))
+        }
+    }
+}
+/// Shows which dashboards would be created, updated or deleted in Grafana
+#[derive(StructOpt, Debug)]
+pub struct PlanArguments {
+    #[structopt(flatten)]
+    grafana: GrafanaArguments,
+
+    /// Id of the Grafana folder, overrides the config file [default: 0]
+    #[structopt(long = "folder")]
+    folder: Option<i64>,
 
-    #[structopt(long = "token", default_value = "graylog")]
-    token: String,
+    /// File mapping Graylog dashboards to the Grafana dashboards written for them
+    #[structopt(
+        long = "state",
+        default_value = "graylog-to-grafana.state.json",
+        parse(from_os_str)
+    )]
+    state: PathBuf,
+}
+/// Creates, updates and deletes dashboards in Grafana and records them in the state file
+#[derive(StructOpt, Debug)]
+pub struct ApplyArguments {
+    #[structopt(flatten)]
+    plan: PlanArguments,
+
+    #[structopt(flatten)]
+    backup: BackupArguments,
+
+    /// Overwrite dashboards which were changed in Grafana since the last apply
+    #[structopt(long = "force")]
+    force: bool,
+}
+/// Allows to upload dashboards from a backup to Grafana again
+#[derive(StructOpt, Debug)]
+pub struct RestoreArguments {
+    #[structopt(flatten)]
+    grafana: GrafanaArguments,
+
+    /// Backup directory or single dashboard backup file
+    #[structopt(name = "backup", parse(from_os_str))]
+    backup: PathBuf,
+
+    /// Restore into this folder instead of the original one
+    #[structopt(long = "folder")]
+    folder: Option<i64>,
+}
+/// Prints the intermediate representation the dashboards are parsed into
+#[derive(StructOpt, Debug)]
+pub struct DumpIrArguments {
+    /// Write into this file instead of stdout
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+/// Converts Grafana dashboards back into a Graylog content pack
+#[derive(StructOpt, Debug)]
+pub struct ReverseArguments {
+    /// Grafana dashboard json files, or directories containing them
+    #[structopt(name = "dashboards", parse(from_os_str), required = true)]
+    dashboards: Vec<PathBuf>,
+
+    /// Write the content pack into this file instead of stdout
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Name of the content pack
+    #[structopt(long = "name", default_value = "grafana")]
+    name: String,
+}
+/// Periodically converts dashboards and pushes changed ones to Grafana
+#[derive(StructOpt, Debug)]
+pub struct SyncArguments {
+    #[structopt(flatten)]
+    grafana: GrafanaArguments,
 
-    #[structopt(long = "folder", default_value = "0")]
-    folder: i64,
+    /// Id of the Grafana folder, overrides the config file [default: 0]
+    #[structopt(long = "folder")]
+    folder: Option<i64>,
+
+    /// Seconds between two sync cycles
+    #[structopt(long = "interval", default_value = "300")]
+    interval: u64,
+
+    /// File recording the dashboards pushed, shared with plan and apply
+    #[structopt(
+        long = "state",
+        default_value = "graylog-to-grafana.state.json",
+        parse(from_os_str)
+    )]
+    state: PathBuf,
+
+    /// Name of the content pack read from the Graylog API, tagged on the dashboards,
+    /// overrides the config file [default: graylog]
+    #[structopt(long = "content-pack-name")]
+    content_pack_name: Option<String>,
+}
+#[derive(Debug, StructOpt)]
+pub enum AlertsCommand {
+    /// Writes the alert rules as Grafana provisioning file
+    #[structopt(name = "generate")]
+    Generate(GenerateAlertsArguments),
+
+    /// Creates the alert rules through the Grafana alerting provisioning API
+    #[structopt(name = "add")]
+    Add(AddAlertsArguments),
+}
+/// Writes the alert rules as Grafana provisioning file
+#[derive(StructOpt, Debug)]
+pub struct GenerateAlertsArguments {
+    /// Output provisioning file (yaml)
+    #[structopt(name = "output", parse(from_os_str))]
+    output: PathBuf,
+
+    /// Title of the folder the rules are put into
+    #[structopt(long = "folder", default_value = "Graylog")]
+    folder: String,
+
+    /// Uid of the Elasticsearch datasource
+    #[structopt(long = "datasource-uid")]
+    datasource_uid: String,
+}
+/// Creates the alert rules through the Grafana alerting provisioning API
+#[derive(StructOpt, Debug)]
+pub struct AddAlertsArguments {
+    #[structopt(flatten)]
+    grafana: GrafanaArguments,
+
+    /// Uid of the folder the rules are put into
+    #[structopt(long = "folder-uid")]
+    folder_uid: String,
+
+    /// Uid of the Elasticsearch datasource, looked up by `--datasource` if missing
+    #[structopt(long = "datasource-uid")]
+    datasource_uid: Option<String>,
+}
+#[derive(Debug, StructOpt)]
+pub enum EventsCommand {
+    /// Writes alert rules, contact points and notification policies as Grafana provisioning file
+    #[structopt(name = "generate")]
+    Generate(GenerateEventsArguments),
+
+    /// Creates alert rules, contact points and notification policies through the Grafana API
+    #[structopt(name = "add")]
+    Add(AddEventsArguments),
+}
+/// Writes alert rules, contact points and notification policies as Grafana provisioning file
+#[derive(StructOpt, Debug)]
+pub struct GenerateEventsArguments {
+    #[structopt(flatten)]
+    alerts: GenerateAlertsArguments,
+
+    /// Contact point receiving all alerts not routed to a converted notification
+    #[structopt(
+        long = "default-contact-point",
+        default_value = "grafana-default-email"
+    )]
+    default_contact_point: String,
+
+    /// Write the notification policy tree, Grafana replaces its whole tree by it when provisioning
+    #[structopt(long = "replace-policies")]
+    replace_policies: bool,
+}
+/// Creates alert rules, contact points and notification policies through the Grafana API
+#[derive(StructOpt, Debug)]
+pub struct AddEventsArguments {
+    #[structopt(flatten)]
+    alerts: AddAlertsArguments,
 }
 #[derive(Debug, StructOpt)]
 pub enum Command {
@@ -37,6 +502,195 @@ pub enum Command {
     /// Allows to add automatically dashboards to Grafana
     #[structopt(name = "add")]
     Add(AddArguments),
+
+    /// Allows to upload dashboards from a backup to Grafana again
+    #[structopt(name = "restore")]
+    Restore(RestoreArguments),
+
+    /// Shows which dashboards would be created, updated or deleted in Grafana
+    #[structopt(name = "plan")]
+    Plan(PlanArguments),
+
+    /// Creates, updates and deletes dashboards in Grafana and records them in the state file
+    #[structopt(name = "apply")]
+    Apply(ApplyArguments),
+
+    /// Periodically converts dashboards and pushes changed ones to Grafana
+    #[structopt(name = "sync")]
+    Sync(SyncArguments),
+
+    /// Converts alert conditions of streams into Grafana alert rules
+    #[structopt(name = "alerts")]
+    Alerts(AlertsCommand),
+
+    /// Converts event definitions and notifications (Graylog 3.1+) into Grafana alerting
+    #[structopt(name = "events")]
+    Events(EventsCommand),
+
+    /// Prints the intermediate representation the dashboards are parsed into
+    #[structopt(name = "dump-ir")]
+    DumpIr(DumpIrArguments),
+
+    /// Converts Grafana dashboards back into a Graylog content pack
+    #[structopt(name = "reverse")]
+    Reverse(ReverseArguments),
+}
+
+/// Credentials used when reading from the Graylog API instead of a content pack
+#[derive(StructOpt, Debug)]
+pub struct GraylogCredentialArguments {
+    /// Graylog access token, prefer the environment variable or --graylog-token-file
+    #[structopt(
+        long = "graylog-token",
+        env = "GRAYLOG_TOKEN",
+        raw(hide_env_values = "true")
+    )]
+    graylog_token: Option<Secret>,
+
+    /// File containing the Graylog access token
+    #[structopt(long = "graylog-token-file", parse(from_os_str))]
+    graylog_token_file: Option<PathBuf>,
+
+    /// Graylog user for basic auth instead of an access token
+    #[structopt(long = "graylog-user", env = "GRAYLOG_USER")]
+    graylog_user: Option<String>,
+
+    /// Graylog password, prefer the environment variable or --graylog-password-file
+    #[structopt(
+        long = "graylog-password",
+        env = "GRAYLOG_PASSWORD",
+        raw(hide_env_values = "true")
+    )]
+    graylog_password: Option<Secret>,
+
+    /// File containing the Graylog password
+    #[structopt(long = "graylog-password-file", parse(from_os_str))]
+    graylog_password_file: Option<PathBuf>,
+}
+
+impl GraylogCredentialArguments {
+    fn credential_sources(&self) -> CredentialSources {
+        CredentialSources {
+            token: self.graylog_token.clone(),
+            token_file: self.graylog_token_file.clone(),
+            user: self.graylog_user.clone(),
+            password: self.graylog_password.clone(),
+            password_file: self.graylog_password_file.clone(),
+        }
+    }
+}
+
+/// What skipped widgets, approximated metrics and other conversion issues lead to
+#[derive(StructOpt, Debug)]
+pub struct PolicyArguments {
+    /// Fail the run on every conversion issue without an action of its own
+    #[structopt(long = "strict", conflicts_with = "no_strict")]
+    strict: bool,
+
+    /// Only warn about conversion issues without an action of their own, overrides the config file
+    #[structopt(long = "no-strict")]
+    no_strict: bool,
+
+    /// Action for widgets without Grafana counterpart
+    #[structopt(
+        long = "on-skipped-widget",
+        raw(possible_values = r#"&["warn", "fail-dashboard", "fail-run"]"#)
+    )]
+    on_skipped_widget: Option<policy::Action>,
+
+    /// Action for metrics approximated by a different function
+    #[structopt(
+        long = "on-approximated-metric",
+        raw(possible_values = r#"&["warn", "fail-dashboard", "fail-run"]"#)
+    )]
+    on_approximated_metric: Option<policy::Action>,
+
+    /// Action for queries with syntax errors
+    #[structopt(
+        long = "on-unparseable-query",
+        raw(possible_values = r#"&["warn", "fail-dashboard", "fail-run"]"#)
+    )]
+    on_unparseable_query: Option<policy::Action>,
+
+    /// Action for widgets extending past the Grafana grid
+    #[structopt(
+        long = "on-layout-overflow",
+        raw(possible_values = r#"&["warn", "fail-dashboard", "fail-run"]"#)
+    )]
+    on_layout_overflow: Option<policy::Action>,
+}
+
+impl PolicyArguments {
+    /// First failure policy option given on the command line.
+    fn given(&self) -> Option<&'static str> {
+        if self.strict {
+            Some("--strict")
+        } else if self.no_strict {
+            Some("--no-strict")
+        } else if self.on_skipped_widget.is_some() {
+            Some("--on-skipped-widget")
+        } else if self.on_approximated_metric.is_some() {
+            Some("--on-approximated-metric")
+        } else if self.on_unparseable_query.is_some() {
+            Some("--on-unparseable-query")
+        } else if self.on_layout_overflow.is_some() {
+            Some("--on-layout-overflow")
+        } else {
+            None
+        }
+    }
+}
+
+/// TLS, proxy and timeouts for connections to Grafana and Graylog
+#[derive(StructOpt, Debug)]
+pub struct ConnectionArguments {
+    /// PEM file with CA certificates trusted in addition to the system ones
+    #[structopt(long = "ca-cert", parse(from_os_str))]
+    ca_cert: Option<PathBuf>,
+
+    /// PKCS#12 file with client certificate and key for mutual TLS
+    #[structopt(long = "client-cert", parse(from_os_str))]
+    client_cert: Option<PathBuf>,
+
+    /// Password of the client certificate file
+    #[structopt(
+        long = "client-cert-password",
+        env = "CLIENT_CERT_PASSWORD",
+        raw(hide_env_values = "true")
+    )]
+    client_cert_password: Option<Secret>,
+
+    /// Accept invalid certificates and host names, only for test setups
+    #[structopt(long = "insecure")]
+    insecure: bool,
+
+    /// Proxy for all requests, e.g. http://proxy:3128
+    #[structopt(long = "proxy")]
+    proxy: Option<String>,
+
+    /// Seconds a request may take
+    #[structopt(long = "timeout")]
+    timeout: Option<u64>,
+
+    /// Seconds connecting may take
+    #[structopt(long = "connect-timeout")]
+    connect_timeout: Option<u64>,
+
+    /// How often failed requests are retried [default: 3]
+    #[structopt(long = "retries")]
+    retries: Option<u32>,
+}
+
+/// Graylog events shown as annotations on every converted dashboard
+#[derive(StructOpt, Debug)]
+pub struct EventAnnotationArguments {
+    /// Elasticsearch datasource for the Graylog events index (`gl-events_*`), enables event annotations
+    #[structopt(long = "events-datasource")]
+    events_datasource: Option<String>,
+
+    /// Query selecting the events shown as annotations
+    #[structopt(long = "events-query", default_value = "*")]
+    events_query: String,
 }
 
 #[derive(Debug, StructOpt)]
@@ -44,76 +698,726 @@ pub enum Command {
 pub struct ApplicationArguments {
     /// Graylog content pack to process
     #[structopt(name = "input", parse(from_os_str))]
-    input: PathBuf,
+    input: Option<PathBuf>,
+
+    /// Configuration file (TOML or YAML) with defaults and per-dashboard settings
+    #[structopt(long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
 
-    #[structopt(long = "datasource", default_value = "graylog")]
-    datasource: String,
+    /// Write a report on how faithfully each widget was converted into this file
+    #[structopt(long = "report", parse(from_os_str))]
+    report: Option<PathBuf>,
 
-    /// Graylog url
+    /// Format of the conversion report
+    #[structopt(
+        long = "report-format",
+        default_value = "json",
+        raw(possible_values = r#"&["json", "markdown", "html"]"#)
+    )]
+    report_format: report::Format,
+
+    /// Elasticsearch datasource, overrides the config file [default: graylog]
+    #[structopt(long = "datasource")]
+    datasource: Option<String>,
+
+    /// Graylog url, used for drilldown links, required when converting dashboards
     #[structopt(long = "graylog-url")]
-    graylog_url: String,
+    graylog_url: Option<String>,
+
+    #[structopt(flatten)]
+    graylog_credentials: GraylogCredentialArguments,
+
+    #[structopt(flatten)]
+    event_annotations: EventAnnotationArguments,
+
+    #[structopt(flatten)]
+    connection: ConnectionArguments,
+
+    #[structopt(flatten)]
+    policy: PolicyArguments,
 
     #[structopt(subcommand)]
     command: Command,
 }
 
+impl ApplicationArguments {
+    /// Fills the options not given on the command line from the config file.
+    fn apply_config(&mut self, config: &Config) {
+        if self.graylog_url.is_none() {
+            self.graylog_url = config.graylog.url.clone();
+        }
+        if self.graylog_credentials.credential_sources().is_empty() {
+            let sources = config.graylog.credentials();
+            self.graylog_credentials = GraylogCredentialArguments {
+                graylog_token: sources.token,
+                graylog_token_file: sources.token_file,
+                graylog_user: sources.user,
+                graylog_password: sources.password,
+                graylog_password_file: sources.password_file,
+            };
+        }
+        if let Some(grafana) = self.command.grafana_mut() {
+            if grafana.url.is_none() {
+                grafana.url = config.grafana.url.clone();
+            }
+            if grafana.credential_sources().is_empty() {
+                let sources = config.grafana.credentials();
+                grafana.token = sources.token;
+                grafana.token_file = sources.token_file;
+                grafana.user = sources.user;
+                grafana.password = sources.password;
+                grafana.password_file = sources.password_file;
+            }
+        }
+    }
+
+    fn graylog_client(
+        &self,
+        url: &str,
+        connection: &ConnectionOptions,
+    ) -> Result<client::GraylogClient, Box<dyn Error>> {
+        let credentials = self
+            .graylog_credentials
+            .credential_sources()
+            .credentials()?;
+        client::GraylogClient::new(url, credentials, connection)
+    }
+
+    fn connection_options(&self, config: &Config) -> ConnectionOptions {
+        let cli = &self.connection;
+        let file = config.connection.clone();
+        ConnectionOptions {
+            ca_cert: cli.ca_cert.clone().or(file.ca_cert),
+            client_cert: cli.client_cert.clone().or(file.client_cert),
+            client_cert_password: cli
+                .client_cert_password
+                .clone()
+                .or(file.client_cert_password),
+            insecure: cli.insecure || file.insecure,
+            proxy: cli.proxy.clone().or(file.proxy),
+            timeout: cli.timeout.or(file.timeout),
+            connect_timeout: cli.connect_timeout.or(file.connect_timeout),
+            retries: cli.retries.or(file.retries),
+        }
+    }
+
+    fn failure_policy(&self, config: &Config) -> FailurePolicy {
+        let cli = &self.policy;
+        let strict = match (cli.strict, cli.no_strict) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+        config.policy.merge(&FailurePolicy {
+            strict,
+            skipped_widget: cli.on_skipped_widget,
+            approximated_metric: cli.on_approximated_metric,
+            unparseable_query: cli.on_unparseable_query,
+            layout_overflow: cli.on_layout_overflow,
+        })
+    }
+
+    fn conversion_options(&self, config: &Config) -> ConversionOptions {
+        let defaults = ConversionOptions::default();
+        ConversionOptions {
+            datasource: self
+                .datasource
+                .clone()
+                .or_else(|| config.defaults.datasource.clone())
+                .unwrap_or(defaults.datasource),
+            graylog_url: self.graylog_url.clone(),
+            events_datasource: self.event_annotations.events_datasource.clone(),
+            events_query: self.event_annotations.events_query.clone(),
+            time_field: config.time_field.clone().unwrap_or(defaults.time_field),
+            link_fields: config.link_fields.clone().unwrap_or(defaults.link_fields),
+            defaults: config.defaults.clone(),
+            dashboards: config.dashboards.clone(),
+            overrides: DashboardSettings {
+                datasource: self.datasource.clone(),
+                folder: self.command.folder(),
+                folder_title: self.command.folder_title(),
+                ..DashboardSettings::default()
+            },
+        }
+    }
+}
+
+impl Command {
+    fn grafana_mut(&mut self) -> Option<&mut GrafanaArguments> {
+        match self {
+            Command::Generate(_) | Command::Alerts(AlertsCommand::Generate(_)) => None,
+            Command::DumpIr(_) | Command::Reverse(_) => None,
+            Command::Events(EventsCommand::Generate(_)) => None,
+            Command::Add(add) => Some(&mut add.grafana),
+            Command::Restore(restore) => Some(&mut restore.grafana),
+            Command::Plan(plan) => Some(&mut plan.grafana),
+            Command::Apply(apply) => Some(&mut apply.plan.grafana),
+            Command::Sync(sync) => Some(&mut sync.grafana),
+            Command::Alerts(AlertsCommand::Add(add)) => Some(&mut add.grafana),
+            Command::Events(EventsCommand::Add(add)) => Some(&mut add.alerts.grafana),
+        }
+    }
+
+    /// Folder given on the command line for the converted dashboards.
+    fn folder(&self) -> Option<i64> {
+        match self {
+            Command::Add(add) => add.folder,
+            Command::Plan(plan) => plan.folder,
+            Command::Apply(apply) => apply.plan.folder,
+            Command::Sync(sync) => sync.folder,
+            _ => None,
+        }
+    }
+
+    /// Whether the command converts dashboards, the failure policy only applies to those.
+    fn converts_dashboards(&self) -> bool {
+        matches!(
+            self,
+            Command::Generate(_)
+                | Command::Add(_)
+                | Command::Plan(_)
+                | Command::Apply(_)
+                | Command::Sync(_)
+        )
+    }
+
+    /// Dashboards the conversion report describes.
+    fn report_backend(&self) -> report::Backend {
+        match self {
+            Command::Generate(generate) => match generate.format {
+                OutputFormat::Perses => report::Backend::Perses,
+                OutputFormat::SavedObjects => report::Backend::SavedObjects,
+                _ => report::Backend::Grafana,
+            },
+            _ => report::Backend::Grafana,
+        }
+    }
+
+    /// Folder title given on the command line for generated provisioning files.
+    fn folder_title(&self) -> Option<String> {
+        match self {
+            Command::Generate(generate) => generate.folder_title.clone(),
+            _ => None,
+        }
+    }
+}
+
 fn main() {
     env_logger::init();
 
-    let opt: &ApplicationArguments = &ApplicationArguments::from_args();
-    let u = read_content_pack_from_file(&opt.input).unwrap();
-    let dashboards: Vec<_> = u
-        .dashboards
-        .into_iter()
-        .map(|t| grafana::Dashboard::create_dashboard_from_graylog(t, opt))
-        .collect();
+    let exit_code = run().unwrap_or_else(|e| {
+        error!("{}", e);
+        EXIT_ERROR
+    });
+    std::process::exit(exit_code);
+}
 
-    match &opt.command {
-        Command::Generate(generate) => {
-            dashboards
-                .iter()
-                .for_each(|s| write_grafana_dashboard(s, &generate).unwrap());
+/// Runs the command, returning the exit code for the failure policy's outcome.
+fn run() -> Result<i32, Box<dyn Error>> {
+    let mut opt = ApplicationArguments::from_args();
+    let config = match &opt.config {
+        Some(path) => Config::load(path).unwrap_or_else(|e| invalid_config(path, &*e)),
+        None => Config::default(),
+    };
+    opt.apply_config(&config);
+    let opt = &opt;
+    let options = &opt.conversion_options(&config);
+    let connection = &opt.connection_options(&config);
+    let policy = opt.failure_policy(&config);
+    let report_given = opt.report.as_ref().map(|_| "--report");
+    if let Some(name) = opt
+        .policy
+        .given()
+        .or(report_given)
+        .filter(|_| !opt.command.converts_dashboards())
+    {
+        conflicting_argument(name);
+    }
+    if opt.graylog_url.is_none() && opt.command.converts_dashboards() {
+        missing_argument("--graylog-url");
+    }
+
+    if let Command::Restore(restore) = &opt.command {
+        let client = restore.grafana.client(connection);
+        backup::restore(&client, &restore.backup, restore.folder)?;
+        return Ok(0);
+    }
+
+    if let Command::Sync(sync) = &opt.command {
+        let client = sync.grafana.client(connection);
+        let name = sync
+            .content_pack_name
+            .clone()
+            .or_else(|| config.graylog.content_pack_name.clone())
+            .unwrap_or_else(|| "graylog".to_string());
+        let fetch = || -> Result<_, Box<dyn Error>> {
+            let content_pack = match (&opt.input, &opt.graylog_url) {
+                (Some(input), _) => read_content_pack_from_file(input)?,
+                (None, Some(url)) => opt
+                    .graylog_client(url, connection)?
+                    .get_content_pack(&name)?,
+                (None, None) => missing_argument("<input>' or '--graylog-url"),
+            };
+            let mut ir = parse(content_pack)?;
+            if let Some(path) = &opt.report {
+                report::Report::new(&ir, opt.command.report_backend())
+                    .write(path, opt.report_format)?;
+            }
+            if policy.apply(&mut ir).failed_run {
+                return Err("Conversion failed by the failure policy".into());
+            }
+            convert_ir(&ir, options)
+        };
+        sync::run(
+            &client,
+            Duration::from_secs(sync.interval),
+            &sync.state,
+            fetch,
+        )?;
+        return Ok(0);
+    }
+
+    if let Command::Reverse(reverse) = &opt.command {
+        write_content_pack(reverse)?;
+        return Ok(0);
+    }
+
+    if let Command::Events(events) = &opt.command {
+        write_or_add_events(events, opt, options, connection)?;
+        return Ok(0);
+    }
+
+    let input = opt
+        .input
+        .as_ref()
+        .unwrap_or_else(|| missing_argument("<input>"));
+    let u = read_content_pack_from_file(input)?;
+    if let Command::Alerts(alerts) = &opt.command {
+        write_or_add_alerts(&u, alerts, options, connection)?;
+        return Ok(0);
+    }
+    let mut ir = parse(u)?;
+    if let Some(path) = &opt.report {
+        report::Report::new(&ir, opt.command.report_backend()).write(path, opt.report_format)?;
+    }
+    if let Command::DumpIr(dump) = &opt.command {
+        match &dump.output {
+            Some(path) => serde_json::to_writer_pretty(File::create(path)?, &ir)?,
+            None => serde_json::to_writer_pretty(std::io::stdout(), &ir)?,
         }
+        return Ok(0);
+    }
+    let outcome = policy.apply(&mut ir);
+    if outcome.failed_run {
+        error!("Conversion failed by the failure policy, no dashboards were converted");
+        return Ok(EXIT_RUN_FAILED);
+    }
+    let failed = &outcome.failed_dashboards;
+    let exit_code = if failed.is_empty() {
+        0
+    } else {
+        error!(
+            "Dashboards left out by the failure policy: {}",
+            failed.join(", ")
+        );
+        EXIT_DASHBOARDS_FAILED
+    };
+    let content_pack = ir.name.clone();
+    let dashboards = convert_ir(&ir, options)?;
+
+    match &opt.command {
+        Command::Generate(generate) => match generate.format {
+            OutputFormat::Json => {
+                for s in &dashboards {
+                    write_grafana_dashboard(s, s, generate)?;
+                }
+            }
+            OutputFormat::Export => {
+                for s in &dashboards {
+                    write_grafana_dashboard(s, &export::SharedDashboard::new(s), generate)?;
+                }
+            }
+            OutputFormat::V2 => {
+                for s in &dashboards {
+                    let resource =
+                        dashboard_v2::resource(s, &dashboard_v2::ResourceOptions::default())?;
+                    write_grafana_dashboard(s, &resource, generate)?;
+                }
+            }
+            OutputFormat::Provisioning => provisioning::write(
+                &generate.output,
+                &generate.provisioning_path,
+                &content_pack,
+                &dashboards,
+                generate.provenance,
+            )?,
+            OutputFormat::Kubernetes => kubernetes::write(
+                &generate.output,
+                &dashboards,
+                &generate.kubernetes.options()?,
+                generate.kubernetes.single_file,
+            )?,
+            OutputFormat::Perses => perses::write(
+                &generate.output,
+                &ir,
+                options,
+                &generate.perses_project,
+                generate.perses_syntax,
+            )?,
+            OutputFormat::SavedObjects => saved_objects::write(
+                &generate.output,
+                &ir,
+                options,
+                &saved_objects::IndexPattern::new(&generate.index_pattern, &options.time_field),
+            )?,
+            OutputFormat::Terraform => {
+                terraform::write(&generate.output, &dashboards, generate.terraform_syntax)?
+            }
+        },
         Command::Add(add) => {
-            dashboards
+            let client = add.grafana.client(connection);
+            let stale = if add.prune {
+                find_stale_dashboards(&client, &content_pack, &dashboards, failed)?
+            } else {
+                vec![]
+            };
+            if add.dry_run {
+                for s in &dashboards {
+                    let existing = client.find_dashboard(s, s.folder_id)?;
+                    let existing = existing.as_ref().map(|e| &e["dashboard"]);
+                    print!("{}", diff::DashboardDiff::new(s, existing));
+                }
+                for hit in &stale {
+                    println!("- dashboard {} (uid {})", hit["title"], hit["uid"]);
+                }
+                return Ok(exit_code);
+            }
+            let backup = add.backup.backup();
+            let dashboards = dashboards
                 .into_iter()
                 .map(|s| grafana::ApiDashboard {
+                    folder_id: s.folder_id,
                     dashboard: s,
-                    folder_id: add.folder,
                     overwrite: true,
                 })
-                .for_each(|dashboard| {
-                    let url = format!("{}{}", add.url, "/api/dashboards/db/");
-                    let client = Client::new();
-                    client
-                        .post(&url)
-                        .header("Authorization", format!("{} {}", "Bearer", add.token))
-                        .json(&dashboard)
-                        .send()
-                        .unwrap();
-                }); //83
+                .collect();
+            upload::upload(
+                &client,
+                dashboards,
+                backup.as_ref(),
+                add.concurrency,
+                &add.upload_api(&client)?,
+            )?;
+            for hit in &stale {
+                let uid = hit["uid"].as_str().unwrap_or_default();
+                if let Some(backup) = &backup {
+                    if let Some(existing) = client.get_dashboard(uid)? {
+                        backup.save(&existing)?;
+                    }
+                }
+                client.delete_dashboard(uid)?;
+                info!("Pruned dashboard {} (uid {})", hit["title"], uid);
+            }
+        }
+        Command::Plan(plan) => {
+            let (_, _, plan) = create_plan(plan, dashboards, failed, connection)?;
+            print!("{}", plan);
         }
+        Command::Apply(apply) => {
+            let (client, mut state, plan) =
+                create_plan(&apply.plan, dashboards, failed, connection)?;
+            print!("{}", plan);
+            if !plan.has_changes() {
+                return Ok(exit_code);
+            }
+            let backup = apply.backup.backup();
+            plan.apply(
+                &client,
+                &mut state,
+                &apply.plan.state,
+                backup.as_ref(),
+                apply.force,
+            )?;
+        }
+        Command::Restore(_)
+        | Command::Sync(_)
+        | Command::Alerts(_)
+        | Command::Events(_)
+        | Command::DumpIr(_)
+        | Command::Reverse(_) => unreachable!(),
     }
+    Ok(exit_code)
+}
+
+fn missing_argument(name: &str) -> ! {
+    clap::Error::with_description(
+        &format!("The argument '{}' is required for this command", name),
+        clap::ErrorKind::MissingRequiredArgument,
+    )
+    .exit()
+}
+
+fn conflicting_argument(name: &str) -> ! {
+    clap::Error::with_description(
+        &format!("The argument '{}' can't be used with this command", name),
+        clap::ErrorKind::ArgumentConflict,
+    )
+    .exit()
+}
+
+fn invalid_config(path: &Path, error: &dyn Error) -> ! {
+    clap::Error::with_description(
+        &format!("Invalid config file {}: {}", path.display(), error),
+        clap::ErrorKind::InvalidValue,
+    )
+    .exit()
+}
+
+fn invalid_connection(error: &dyn Error) -> ! {
+    clap::Error::with_description(
+        &format!("Invalid connection options: {}", error),
+        clap::ErrorKind::InvalidValue,
+    )
+    .exit()
 }
 
-fn write_grafana_dashboard(
+fn invalid_credentials(error: &dyn Error) -> ! {
+    clap::Error::with_description(
+        &format!("Invalid Grafana credentials: {}", error),
+        clap::ErrorKind::InvalidValue,
+    )
+    .exit()
+}
+
+/// Dashboards tagged as converted from `content_pack` which the current run didn't produce,
+/// searched in all folders the converted dashboards go to. Dashboards titled like one in
+/// `failed` still exist in Graylog and are kept.
+fn find_stale_dashboards(
+    client: &client::GrafanaClient,
+    content_pack: &str,
+    dashboards: &[grafana::Dashboard],
+    failed: &[String],
+) -> Result<Vec<serde_json::Value>, Box<dyn Error>> {
+    let tags = grafana::Dashboard::provenance_tags(content_pack);
+    let mut folder_ids: Vec<_> = dashboards.iter().map(|d| d.folder_id).collect();
+    folder_ids.sort_unstable();
+    folder_ids.dedup();
+    let mut stale = vec![];
+    for folder_id in folder_ids {
+        stale.extend(
+            client
+                .search_tagged(&tags, folder_id)?
+                .into_iter()
+                .filter(|hit| {
+                    !dashboards
+                        .iter()
+                        .any(|d| hit["uid"] == d.uid.as_str() || hit["title"] == d.title.as_str())
+                        && !failed.iter().any(|title| hit["title"] == title.as_str())
+                }),
+        );
+    }
+    Ok(stale)
+}
+
+fn create_plan(
+    opt: &PlanArguments,
+    dashboards: Vec<grafana::Dashboard>,
+    failed: &[String],
+    connection: &ConnectionOptions,
+) -> Result<(client::GrafanaClient, state::State, state::Plan), Box<dyn Error>> {
+    let client = opt.grafana.client(connection);
+    let state = state::State::load(&opt.state)?;
+    let plan = state::Plan::new(dashboards, &state, |uid| {
+        Ok(client
+            .get_dashboard(uid)?
+            .and_then(|d| d["dashboard"]["version"].as_i64()))
+    })?
+    .keep(failed);
+    Ok((client, state, plan))
+}
+
+fn write_or_add_alerts(
+    content_pack: &graylog::ContentPack,
+    alerts: &AlertsCommand,
+    options: &ConversionOptions,
+    connection: &ConnectionOptions,
+) -> Result<(), Box<dyn Error>> {
+    match alerts {
+        AlertsCommand::Generate(generate) => {
+            let groups = alerting::RuleGroup::create_rule_groups_from_graylog(
+                content_pack,
+                &generate.datasource_uid,
+                &options.time_field,
+            );
+            let file = File::create(&generate.output)?;
+            serde_yaml::to_writer(file, &alerting::Provisioning::new(groups, &generate.folder))?;
+        }
+        AlertsCommand::Add(add) => {
+            let client = add.grafana.client(connection);
+            let datasource_uid = match &add.datasource_uid {
+                Some(uid) => uid.clone(),
+                None => client.get_datasource_uid(&options.datasource)?,
+            };
+            for group in alerting::RuleGroup::create_rule_groups_from_graylog(
+                content_pack,
+                &datasource_uid,
+                &options.time_field,
+            ) {
+                info!("Adding alert rule group \"{}\"", group.title);
+                client.put_rule_group(&alerting::ApiRuleGroup::new(group, &add.folder_uid))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads event definitions and notifications from the content pack, or from the Graylog API
+/// if no content pack is given. Content pack entities which can't be read are added to `report`.
+fn read_events(
+    opt: &ApplicationArguments,
+    connection: &ConnectionOptions,
+    report: &mut Vec<String>,
+) -> Result<
+    (
+        Vec<graylog::EventDefinition>,
+        Vec<graylog::EventNotification>,
+    ),
+    Box<dyn Error>,
+> {
+    match (&opt.input, &opt.graylog_url) {
+        (Some(input), _) => {
+            let content_pack = read_content_pack_from_file(input)?;
+            Ok((
+                content_pack.event_definitions(report),
+                content_pack.notifications(report),
+            ))
+        }
+        (None, Some(url)) => {
+            let graylog = opt.graylog_client(url, connection)?;
+            Ok((
+                graylog.get_event_definitions()?,
+                graylog.get_notifications()?,
+            ))
+        }
+        (None, None) => missing_argument("<input>' or '--graylog-url"),
+    }
+}
+
+fn write_or_add_events(
+    events: &EventsCommand,
+    opt: &ApplicationArguments,
+    options: &ConversionOptions,
+    connection: &ConnectionOptions,
+) -> Result<(), Box<dyn Error>> {
+    let mut report = vec![];
+    let (definitions, notifications) = read_events(opt, connection, &mut report)?;
+    let conversion_report = match events {
+        EventsCommand::Generate(generate) => {
+            let mut conversion = alerting::EventsConversion::create_from_graylog(
+                &definitions,
+                &notifications,
+                &generate.alerts.datasource_uid,
+                &options.time_field,
+            );
+            let mut provisioning =
+                alerting::Provisioning::new(conversion.groups, &generate.alerts.folder)
+                    .with_contact_points(conversion.contact_points);
+            if generate.replace_policies {
+                let mut policy = alerting::NotificationPolicy::new(&generate.default_contact_point);
+                policy.merge_routes(conversion.routes);
+                provisioning = provisioning.with_policy(policy);
+            } else if !conversion.routes.is_empty() {
+                conversion.report.push(format!(
+                    "Left out {} notification policy routes, provisioning replaces the whole tree: \
+                     pass --replace-policies or merge them with `events add`",
+                    conversion.routes.len()
+                ));
+            }
+            serde_yaml::to_writer(File::create(&generate.alerts.output)?, &provisioning)?;
+            conversion.report
+        }
+        EventsCommand::Add(add) => {
+            let add = &add.alerts;
+            let client = add.grafana.client(connection);
+            let datasource_uid = match &add.datasource_uid {
+                Some(uid) => uid.clone(),
+                None => client.get_datasource_uid(&options.datasource)?,
+            };
+            let conversion = alerting::EventsConversion::create_from_graylog(
+                &definitions,
+                &notifications,
+                &datasource_uid,
+                &options.time_field,
+            );
+            for contact_point in &conversion.contact_points {
+                info!("Adding contact point \"{}\"", contact_point.name);
+                client.put_contact_point(contact_point)?;
+            }
+            for group in conversion.groups {
+                info!("Adding alert rule group \"{}\"", group.title);
+                client.put_rule_group(&alerting::ApiRuleGroup::new(group, &add.folder_uid))?;
+            }
+            let mut policy = client.get_notification_policy()?;
+            policy.merge_routes(conversion.routes);
+            client.put_notification_policy(&policy)?;
+            conversion.report
+        }
+    };
+    for line in report.iter().chain(&conversion_report) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn write_grafana_dashboard<T: Serialize>(
     s: &grafana::Dashboard,
+    json: &T,
     opt: &GenerateArguments,
-) -> Result<(), Box<Error>> {
-    let filename = format!(
-        "{}.json",
-        s.title.replace(" ", "_").replace("/", "_").to_lowercase()
-    );
+) -> Result<(), Box<dyn Error>> {
     let mut path = opt.output.clone();
-    path.push(filename);
+    path.push(s.file_name());
     let file = File::create(path)?;
-    serde_json::to_writer_pretty(file, &s)?;
+    serde_json::to_writer_pretty(file, json)?;
+    Ok(())
+}
+
+/// Writes the Graylog content pack for the Grafana dashboards, listing skipped panels on stderr.
+fn write_content_pack(opt: &ReverseArguments) -> Result<(), Box<dyn Error>> {
+    let mut dashboards = vec![];
+    for path in &opt.dashboards {
+        let files = if path.is_dir() {
+            let mut files = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            files.retain(|f| f.extension() == Some("json".as_ref()));
+            files.sort();
+            files
+        } else {
+            vec![path.clone()]
+        };
+        for file in files {
+            dashboards.push(serde_json::from_reader(BufReader::new(File::open(file)?))?);
+        }
+    }
+    let (ir, mut skipped) = reverse::parse(&opt.name, &dashboards);
+    let (content_pack, mut unsupported) = reverse::content_pack(&ir);
+    skipped.append(&mut unsupported);
+    for s in &skipped {
+        warn!(
+            "Skipped panel \"{}\" of dashboard \"{}\": {}",
+            s.panel, s.dashboard, s.reason
+        );
+    }
+    match &opt.output {
+        Some(path) => serde_json::to_writer_pretty(File::create(path)?, &content_pack)?,
+        None => serde_json::to_writer_pretty(std::io::stdout(), &content_pack)?,
+    }
     Ok(())
 }
 
 fn read_content_pack_from_file<P: AsRef<Path>>(
     path: &P,
-) -> Result<graylog::ContentPack, Box<Error>> {
+) -> Result<graylog::ContentPack, Box<dyn Error>> {
     // Open the file in read-only mode with buffer.
     let file = File::open(path)?;
     let reader = BufReader::new(file);
@@ -0,0 +1,268 @@
+use crate::grafana::{Dashboard, GridPos, Link, TimeRange};
+use crate::{ir, ConversionOptions};
+use log::warn;
+use serde_json::{json, Map, Value};
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Perses ships no Elasticsearch plugin, queries and datasources are placeholders naming
+/// these kinds. Perses rejects them until a plugin providing them is installed or the
+/// queries are replaced.
+pub const QUERY_PLUGIN: &str = "ElasticsearchTimeSeriesQuery";
+pub const DATASOURCE_KIND: &str = "ElasticsearchDatasource";
+/// Duration of dashboards without time range of their own, the one Grafana dashboards get.
+const DEFAULT_DURATION: &str = "2d";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    Json,
+    Yaml,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(syntax: &str) -> Result<Syntax, String> {
+        match syntax {
+            "json" => Ok(Syntax::Json),
+            "yaml" => Ok(Syntax::Yaml),
+            _ => Err(format!("Unknown Perses syntax {}", syntax)),
+        }
+    }
+}
+
+/// Perses dashboard in `project` for a dashboard of the intermediate representation, with
+/// the settings `opt` has for it. Panels are placed on a grid of the same 24 columns
+/// Grafana uses.
+pub fn dashboard(dash: &ir::Dashboard, opt: &ConversionOptions, project: &str) -> Value {
+    let settings = opt.settings_for(dash);
+    let datasource = settings.datasource.as_ref().unwrap_or(&opt.datasource);
+    let mut panels = Map::new();
+    let mut items = vec![];
+    for (i, panel) in dash.panels.iter().enumerate() {
+        let name = format!("panel{}", i + 1);
+        let grid_pos = GridPos::new_with_layout(&panel.layout);
+        items.push(json!({
+            "x": grid_pos.x,
+            "y": grid_pos.y,
+            "width": grid_pos.w,
+            "height": grid_pos.h,
+            "content": {"$ref": format!("#/spec/panels/{}", name)},
+        }));
+        if !panel.queries.is_empty() {
+            warn!(
+                "Perses panel \"{}\" of dashboard \"{}\" has placeholder {} queries",
+                panel.title, dash.title, QUERY_PLUGIN
+            );
+        }
+        panels.insert(name, self::panel(panel, datasource, opt));
+    }
+    let duration = settings.time.map_or_else(
+        || DEFAULT_DURATION.to_string(),
+        |time| duration(&time, &dash.title),
+    );
+    let mut spec = json!({
+        "display": {"name": dash.title},
+        "duration": duration,
+        "variables": [],
+        "panels": panels,
+        "layouts": [{"kind": "Grid", "spec": {"items": items}}],
+    });
+    if let Some(refresh) = &settings.refresh {
+        spec["refreshInterval"] = json!(refresh);
+    }
+    json!({
+        "kind": "Dashboard",
+        "metadata": {"name": Dashboard::uid_from_title(&dash.title), "project": project},
+        "spec": spec,
+    })
+}
+
+fn panel(panel: &ir::Panel, datasource: &str, opt: &ConversionOptions) -> Value {
+    let queries: Vec<Value> = panel
+        .queries
+        .iter()
+        .map(|query| {
+            json!({
+                "kind": "TimeSeriesQuery",
+                "spec": {
+                    "plugin": {
+                        "kind": QUERY_PLUGIN,
+                        "spec": {
+                            "datasource": {"kind": DATASOURCE_KIND, "name": datasource},
+                            "query": query.query,
+                            "timeField": opt.time_field,
+                            "metric": query.metric,
+                            "buckets": query.buckets,
+                        },
+                    },
+                },
+            })
+        })
+        .collect();
+    let plugin = match panel.visualization {
+        ir::Visualization::Chart { style } => {
+            let visual = match style {
+                ir::ChartStyle::Bar => json!({"display": "bar"}),
+                ir::ChartStyle::Line => json!({"display": "line"}),
+                ir::ChartStyle::Area => json!({"display": "line", "areaOpacity": 0.3}),
+            };
+            json!({"kind": plugin_kind(&panel.visualization), "spec": {"visual": visual}})
+        }
+        ir::Visualization::SingleValue { trend } => {
+            // Single values with a time range show its total, like in Graylog.
+            let calculation = if panel.time_range.is_some() {
+                "sum"
+            } else {
+                "last"
+            };
+            let mut spec = json!({ "calculation": calculation });
+            if trend {
+                spec["sparkline"] = json!({});
+            }
+            json!({"kind": plugin_kind(&panel.visualization), "spec": spec})
+        }
+        ir::Visualization::Pie => json!({
+            "kind": plugin_kind(&panel.visualization),
+            "spec": {"calculation": "sum", "legend": {"position": "right"}},
+        }),
+    };
+    let mut links = vec![];
+    if let (Some(url), Some(query), Some(range)) =
+        (&opt.graylog_url, panel.queries.first(), panel.time_range)
+    {
+        let link = Link::new_graylog_search(url, &query.query, range, &opt.link_fields);
+        links.push(json!({"name": link.title, "url": link.url, "targetBlank": link.target_blank}));
+    }
+    json!({
+        "kind": "Panel",
+        "spec": {
+            "display": {"name": panel.title},
+            "plugin": plugin,
+            "queries": queries,
+            "links": links,
+        },
+    })
+}
+
+/// Kind of the panel plugin showing `visualization`.
+pub fn plugin_kind(visualization: &ir::Visualization) -> &'static str {
+    match visualization {
+        ir::Visualization::Chart { .. } => "TimeSeriesChart",
+        ir::Visualization::SingleValue { .. } => "StatChart",
+        ir::Visualization::Pie => "PieChart",
+    }
+}
+
+/// Perses dashboards always end now and only know a duration, `now-2d` becomes `2d`.
+/// Absolute or rounded times fall back to `DEFAULT_DURATION`, with a warning.
+fn duration(time: &TimeRange, dashboard: &str) -> String {
+    if time.to != "now" {
+        warn!(
+            "Perses dashboard \"{}\" ends now instead of {}",
+            dashboard, time.to
+        );
+    }
+    let relative = time.from.strip_prefix("now-").filter(|duration| {
+        let unit = duration.trim_start_matches(|c: char| c.is_ascii_digit());
+        unit.len() < duration.len() && ["ms", "s", "m", "h", "d", "w", "y"].contains(&unit)
+    });
+    match relative {
+        Some(duration) => duration.to_string(),
+        None => {
+            warn!(
+                "Perses dashboard \"{}\" can't start at {}, it shows the last {}",
+                dashboard, time.from, DEFAULT_DURATION
+            );
+            DEFAULT_DURATION.to_string()
+        }
+    }
+}
+
+/// Writes a Perses dashboard per dashboard of `content_pack` into `dir`.
+pub fn write(
+    dir: &Path,
+    content_pack: &ir::ContentPack,
+    opt: &ConversionOptions,
+    project: &str,
+    syntax: Syntax,
+) -> Result<(), Box<dyn Error>> {
+    for dash in &content_pack.dashboards {
+        let perses = dashboard(dash, opt, project);
+        let name = Dashboard::file_name_for(&dash.title);
+        let stem = name.trim_end_matches(".json");
+        match syntax {
+            Syntax::Json => {
+                let file = File::create(dir.join(format!("{}.json", stem)))?;
+                serde_json::to_writer_pretty(file, &perses)?;
+            }
+            Syntax::Yaml => {
+                let file = File::create(dir.join(format!("{}.yaml", stem)))?;
+                serde_yaml::to_writer(file, &perses)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DashboardSettings;
+
+    #[test]
+    fn panels_and_grid_layout() {
+        let dash = ir::Dashboard::new("Web Errors").with_panel(
+            ir::Panel::new("Errors", ir::Visualization::SingleValue { trend: false })
+                .with_query(ir::Query::new_count("level:3"))
+                .with_time_range(3600)
+                .with_layout(ir::Layout {
+                    row: 1,
+                    col: 2,
+                    width: 1,
+                    height: 1,
+                }),
+        );
+        let opt = ConversionOptions {
+            defaults: DashboardSettings {
+                time: Some(TimeRange {
+                    from: "now-12h".to_string(),
+                    to: "now".to_string(),
+                }),
+                ..DashboardSettings::default()
+            },
+            ..ConversionOptions::default()
+        };
+
+        let perses = super::dashboard(&dash, &opt, "graylog");
+
+        assert_eq!("web-errors-90fa04f0", perses["metadata"]["name"]);
+        assert_eq!("12h", perses["spec"]["duration"]);
+        let panel = &perses["spec"]["panels"]["panel1"]["spec"];
+        assert_eq!("StatChart", panel["plugin"]["kind"]);
+        assert_eq!("sum", panel["plugin"]["spec"]["calculation"]);
+        assert_eq!(
+            "graylog",
+            panel["queries"][0]["spec"]["plugin"]["spec"]["datasource"]["name"]
+        );
+        let item = &perses["spec"]["layouts"][0]["spec"]["items"][0];
+        assert_eq!("#/spec/panels/panel1", item["content"]["$ref"]);
+        assert_eq!(5, item["x"]);
+    }
+
+    #[test]
+    fn duration_from_relative_time_ranges() {
+        let time = |from: &str, to: &str| TimeRange {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+
+        assert_eq!("12h", duration(&time("now-12h", "now"), "Web"));
+        assert_eq!("90m", duration(&time("now-90m", "now-5m"), "Web"));
+        assert_eq!("2d", duration(&time("now-7d/d", "now"), "Web"));
+        assert_eq!("2d", duration(&time("2024-01-01T00:00:00Z", "now"), "Web"));
+        assert_eq!("2d", duration(&time("now-h", "now"), "Web"));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::grafana::Panel;
+use crate::ir::{ContentPack, Dashboard, WarningKind};
+use log::{error, warn};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// What happens when a conversion issue occurs.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// Log the issue and convert the dashboard anyway.
+    Warn,
+    /// Leave out the dashboard, converting the others.
+    FailDashboard,
+    /// Convert none of the dashboards.
+    FailRun,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(action: &str) -> Result<Action, String> {
+        match action {
+            "warn" => Ok(Action::Warn),
+            "fail-dashboard" => Ok(Action::FailDashboard),
+            "fail-run" => Ok(Action::FailRun),
+            _ => Err(format!("Unknown failure action {}", action)),
+        }
+    }
+}
+
+/// Conversion issues a policy decides on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Issue {
+    SkippedWidget,
+    ApproximatedMetric,
+    UnparseableQuery,
+    LayoutOverflow,
+}
+
+impl Issue {
+    /// Issue for a warning, `None` for warnings which are only reported.
+    pub fn from_warning(kind: WarningKind) -> Option<Issue> {
+        match kind {
+            WarningKind::ApproximatedMetric => Some(Issue::ApproximatedMetric),
+            WarningKind::UnparseableQuery => Some(Issue::UnparseableQuery),
+            WarningKind::LayoutOverflow => Some(Issue::LayoutOverflow),
+            WarningKind::TimeRange => None,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Issue::SkippedWidget => "skipped widget",
+            Issue::ApproximatedMetric => "approximated metric",
+            Issue::UnparseableQuery => "unparseable query",
+            Issue::LayoutOverflow => "overflowing layout",
+        })
+    }
+}
+
+/// Action per issue. Issues without an action of their own warn, or fail the run if `strict`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailurePolicy {
+    pub strict: Option<bool>,
+    pub skipped_widget: Option<Action>,
+    pub approximated_metric: Option<Action>,
+    pub unparseable_query: Option<Action>,
+    pub layout_overflow: Option<Action>,
+}
+
+/// Dashboards left out by a policy, and whether it failed the whole run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Outcome {
+    pub failed_dashboards: Vec<String>,
+    pub failed_run: bool,
+}
+
+impl FailurePolicy {
+    pub fn action(&self, issue: Issue) -> Action {
+        let action = match issue {
+            Issue::SkippedWidget => self.skipped_widget,
+            Issue::ApproximatedMetric => self.approximated_metric,
+            Issue::UnparseableQuery => self.unparseable_query,
+            Issue::LayoutOverflow => self.layout_overflow,
+        };
+        action.unwrap_or(if self.strict == Some(true) {
+            Action::FailRun
+        } else {
+            Action::Warn
+        })
+    }
+
+    /// Merges `other` into `self`, values of `other` win.
+    pub fn merge(&self, other: &FailurePolicy) -> FailurePolicy {
+        FailurePolicy {
+            strict: other.strict.or(self.strict),
+            skipped_widget: other.skipped_widget.or(self.skipped_widget),
+            approximated_metric: other.approximated_metric.or(self.approximated_metric),
+            unparseable_query: other.unparseable_query.or(self.unparseable_query),
+            layout_overflow: other.layout_overflow.or(self.layout_overflow),
+        }
+    }
+
+    /// Logs the issues of all dashboards and removes the dashboards failing the policy.
+    pub fn apply(&self, content_pack: &mut ContentPack) -> Outcome {
+        let mut outcome = Outcome::default();
+        content_pack.dashboards.retain(|dashboard| {
+            match self.check(dashboard) {
+                Action::Warn => return true,
+                Action::FailDashboard => {}
+                Action::FailRun => outcome.failed_run = true,
+            }
+            outcome.failed_dashboards.push(dashboard.title.clone());
+            false
+        });
+        outcome
+    }
+
+    /// The strongest action any issue of `dashboard` calls for.
+    fn check(&self, dashboard: &Dashboard) -> Action {
+        let mut issues = vec![];
+        for skipped in &dashboard.skipped {
+            issues.push((Issue::SkippedWidget, &skipped.title, skipped.reason.clone()));
+        }
+        for panel in &dashboard.panels {
+            for warning in panel
+                .warnings
+                .iter()
+                .cloned()
+                .chain(Panel::deviations(panel))
+            {
+                if let Some(issue) = Issue::from_warning(warning.kind) {
+                    issues.push((issue, &panel.title, warning.message));
+                }
+            }
+        }
+        let mut strongest = Action::Warn;
+        for (issue, widget, message) in issues {
+            let action = self.action(issue);
+            if action == Action::Warn {
+                warn!(
+                    "{} in widget \"{}\" of dashboard \"{}\": {}",
+                    issue, widget, dashboard.title, message
+                );
+            } else {
+                error!(
+                    "{} in widget \"{}\" of dashboard \"{}\": {}",
+                    issue, widget, dashboard.title, message
+                );
+            }
+            if action > strongest {
+                strongest = action;
+            }
+        }
+        strongest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::fixtures::{approximated_panel, skipped_map};
+    use crate::ir::{self, Visualization};
+
+    fn content_pack() -> ContentPack {
+        let mut skipping = ir::Dashboard::new("Skipping");
+        skipping.skipped.push(skipped_map("Map"));
+        let approximating = ir::Dashboard::new("Approximating")
+            .with_panel(approximated_panel("Latency", Visualization::Pie));
+        ContentPack {
+            name: "web".to_string(),
+            dashboards: vec![skipping, approximating, ir::Dashboard::new("Clean")],
+        }
+    }
+
+    #[test]
+    fn actions_per_issue() {
+        let policy = FailurePolicy {
+            skipped_widget: Some(Action::FailDashboard),
+            ..FailurePolicy::default()
+        };
+        let mut pack = content_pack();
+
+        let outcome = policy.apply(&mut pack);
+
+        assert_eq!(vec!["Skipping"], outcome.failed_dashboards);
+        assert!(!outcome.failed_run);
+        assert_eq!(2, pack.dashboards.len());
+    }
+
+    #[test]
+    fn strict_fails_run_unless_overridden() {
+        let strict = FailurePolicy {
+            strict: Some(true),
+            ..FailurePolicy::default()
+        };
+        let lenient = strict.merge(&FailurePolicy {
+            skipped_widget: Some(Action::Warn),
+            approximated_metric: Some(Action::FailDashboard),
+            ..FailurePolicy::default()
+        });
+
+        assert!(strict.apply(&mut content_pack()).failed_run);
+        let outcome = lenient.apply(&mut content_pack());
+        assert!(!outcome.failed_run);
+        assert_eq!(vec!["Approximating"], outcome.failed_dashboards);
+        assert_eq!(Action::FailRun, lenient.action(Issue::LayoutOverflow));
+        let relaxed = strict.merge(&FailurePolicy {
+            strict: Some(false),
+            ..FailurePolicy::default()
+        });
+        assert_eq!(Action::Warn, relaxed.action(Issue::LayoutOverflow));
+    }
+}
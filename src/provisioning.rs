@@ -0,0 +1,229 @@
+use crate::grafana::Dashboard;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Name of the dashboard provider config written next to the dashboards.
+pub const PROVIDER_FILE: &str = "dashboards.yaml";
+
+/// Dashboard provider config for Grafana's file provisioning, belongs into
+/// `provisioning/dashboards/`.
+#[derive(Serialize, Debug)]
+pub struct ProviderConfig {
+    #[serde(rename = "apiVersion")]
+    pub api_version: u32,
+    pub providers: Vec<Provider>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Provider {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    #[serde(rename = "disableDeletion")]
+    pub disable_deletion: bool,
+    #[serde(rename = "allowUiUpdates")]
+    pub allow_ui_updates: bool,
+    pub options: ProviderOptions,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProviderOptions {
+    /// Directory Grafana reads the dashboards from.
+    pub path: String,
+    /// Subdirectories become folders of the same name.
+    #[serde(rename = "foldersFromFilesStructure")]
+    pub folders_from_files_structure: bool,
+}
+
+impl ProviderConfig {
+    /// Provider reading the dashboards below `path`, with one folder per subdirectory.
+    pub fn new(name: &str, path: &str) -> ProviderConfig {
+        ProviderConfig {
+            api_version: 1,
+            providers: vec![Provider {
+                name: name.to_string(),
+                r#type: "file".to_string(),
+                disable_deletion: false,
+                allow_ui_updates: false,
+                options: ProviderOptions {
+                    path: path.to_string(),
+                    folders_from_files_structure: true,
+                },
+            }],
+        }
+    }
+}
+
+/// Identifies a provisioned dashboard as generated by this tool, written as first key of the
+/// dashboard json.
+#[derive(Serialize, Debug)]
+pub struct Provenance {
+    pub generator: String,
+    pub content_pack: String,
+    pub source: String,
+}
+
+impl Provenance {
+    pub fn new(content_pack: &str, dashboard: &Dashboard) -> Provenance {
+        Provenance {
+            generator: format!("graylog-to-grafana {}", env!("CARGO_PKG_VERSION")),
+            content_pack: content_pack.to_string(),
+            source: dashboard.title.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProvisionedDashboard<'a> {
+    #[serde(rename = "__provenance", skip_serializing_if = "Option::is_none")]
+    provenance: Option<Provenance>,
+    #[serde(flatten)]
+    dashboard: &'a Dashboard,
+}
+
+/// Writes the dashboards into `dir`, each into the subdirectory of its folder, together with
+/// the provider config. `path` is the directory as seen by Grafana.
+pub fn write(
+    dir: &Path,
+    path: &str,
+    content_pack: &str,
+    dashboards: &[Dashboard],
+    provenance: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut files = BTreeSet::new();
+    for dashboard in dashboards {
+        // File names are lowercase, "Web" and "web" in the same folder need different ones.
+        let file = unique_path(dashboard_path(dir, dashboard), |path| files.contains(path));
+        files.insert(file.clone());
+        fs::create_dir_all(file.parent().unwrap_or(dir))?;
+        let provisioned = ProvisionedDashboard {
+            provenance: if provenance {
+                Some(Provenance::new(content_pack, dashboard))
+            } else {
+                None
+            },
+            dashboard,
+        };
+        serde_json::to_writer_pretty(File::create(file)?, &provisioned)?;
+    }
+    let config = ProviderConfig::new(content_pack, path);
+    serde_yaml::to_writer(File::create(dir.join(PROVIDER_FILE))?, &config)?;
+    Ok(())
+}
+
+/// Dashboards without folder title end up in the General folder, Grafana takes the name of
+/// a subdirectory as folder title.
+fn dashboard_path(dir: &Path, dashboard: &Dashboard) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    if let Some(folder) = &dashboard.folder_title {
+        path.push(path_segment(folder));
+    }
+    path.push(path_segment(&dashboard.file_name()));
+    path
+}
+
+/// Keeps letters, digits, spaces, `-`, `_` and `.`, so names can't leave the output
+/// directory. Names of dots only, like `..`, become underscores.
+fn path_segment(name: &str) -> String {
+    let segment: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if segment.chars().all(|c| c == '.') {
+        "_".repeat(segment.len().max(1))
+    } else {
+        segment
+    }
+}
+
+/// Appends a counter to the file name of paths which are already taken.
+fn unique_path<F: Fn(&Path) -> bool>(path: PathBuf, taken: F) -> PathBuf {
+    if !taken(&path) {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (2..)
+        .map(|i| path.with_file_name(format!("{}_{}.json", stem, i)))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dashboards_go_into_folder_directories() {
+        let dir = Path::new("provisioning");
+        let general = Dashboard::new("Web Errors");
+        let nested = Dashboard::new("Web Errors").with_folder_title("Team A/B");
+
+        assert_eq!(
+            Path::new("provisioning/web_errors.json"),
+            dashboard_path(dir, &general)
+        );
+        assert_eq!(
+            Path::new("provisioning/Team A_B/web_errors.json"),
+            dashboard_path(dir, &nested)
+        );
+    }
+
+    #[test]
+    fn folder_titles_stay_inside_the_output_directory() {
+        let dir = Path::new("provisioning");
+        let parent = Dashboard::new("Web").with_folder_title("..");
+        let windows = Dashboard::new("Web").with_folder_title("..\\etc");
+
+        assert_eq!(
+            Path::new("provisioning/__/web.json"),
+            dashboard_path(dir, &parent)
+        );
+        assert_eq!(
+            Path::new("provisioning/.._etc/web.json"),
+            dashboard_path(dir, &windows)
+        );
+    }
+
+    #[test]
+    fn unique_paths_get_a_counter() {
+        let taken = Path::new("provisioning/web.json");
+
+        assert_eq!(
+            Path::new("provisioning/web_2.json"),
+            unique_path(taken.to_path_buf(), |path| path == taken)
+        );
+        assert_eq!(
+            Path::new("provisioning/app.json"),
+            unique_path(
+                Path::new("provisioning/app.json").to_path_buf(),
+                |path| path == taken
+            )
+        );
+    }
+
+    #[test]
+    fn provenance_is_first_key() {
+        let dashboard = Dashboard::new("Web");
+        let provisioned = ProvisionedDashboard {
+            provenance: Some(Provenance::new("pack", &dashboard)),
+            dashboard: &dashboard,
+        };
+
+        let json = serde_json::to_string(&provisioned).unwrap();
+
+        assert!(json.starts_with("{\"__provenance\":{\"generator\":\"graylog-to-grafana "));
+        assert!(json.contains("\"content_pack\":\"pack\",\"source\":\"Web\"},\"uid\""));
+    }
+}
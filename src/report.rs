@@ -0,0 +1,357 @@
+use crate::grafana::{Panel, PanelType};
+use crate::ir;
+use crate::{perses, saved_objects};
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Markdown,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Format, String> {
+        match format {
+            "json" => Ok(Format::Json),
+            "markdown" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            _ => Err(format!("Unknown report format {}", format)),
+        }
+    }
+}
+
+/// Dashboards the widgets are converted into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Grafana,
+    Perses,
+    /// Kibana and OpenSearch Dashboards saved objects.
+    SavedObjects,
+}
+
+impl Backend {
+    /// Grafana panel type, Perses plugin kind or Kibana visualization type.
+    fn target_type(self, visualization: &ir::Visualization) -> String {
+        match self {
+            Backend::Grafana => PanelType::from_ir(visualization).id().to_string(),
+            Backend::Perses => perses::plugin_kind(visualization).to_string(),
+            Backend::SavedObjects => saved_objects::visualization_type(visualization).to_string(),
+        }
+    }
+
+    /// What the backend shows differently than the panel describes.
+    fn deviations(self, panel: &ir::Panel) -> Vec<String> {
+        let mut deviations = Panel::deviations(panel);
+        if self != Backend::Grafana {
+            // Only Grafana panels have time ranges of their own.
+            deviations.retain(|warning| warning.kind != ir::WarningKind::TimeRange);
+            if let Some(range) = panel.time_range {
+                deviations.insert(
+                    0,
+                    ir::Warning {
+                        kind: ir::WarningKind::TimeRange,
+                        message: format!(
+                            "time range of {}s replaced by the dashboard time range",
+                            range
+                        ),
+                    },
+                );
+            }
+        }
+        let mut messages: Vec<String> = deviations.into_iter().map(|w| w.message).collect();
+        match self {
+            Backend::Perses if !panel.queries.is_empty() => {
+                messages.push("queries use a placeholder plugin".to_string())
+            }
+            Backend::SavedObjects if panel.queries.len() > 1 => {
+                messages.push("only the first query is kept".to_string())
+            }
+            _ => {}
+        }
+        messages
+    }
+}
+
+/// How close a panel comes to its widget.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Fidelity {
+    Exact,
+    Approximate,
+    Skipped,
+}
+
+/// What became of every widget of a conversion, for review by the dashboard owners.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub content_pack: String,
+    pub dashboards: Vec<DashboardReport>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DashboardReport {
+    pub title: String,
+    pub widgets: Vec<WidgetReport>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WidgetReport {
+    pub title: String,
+    pub source_type: String,
+    /// Grafana panel type, `None` for skipped widgets.
+    pub target_type: Option<String>,
+    pub fidelity: Fidelity,
+    /// Why the widget was skipped.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+    /// What was approximated or dropped.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    /// Report on converting the dashboards of `content_pack` into dashboards of `backend`.
+    pub fn new(content_pack: &ir::ContentPack, backend: Backend) -> Report {
+        Report {
+            content_pack: content_pack.name.clone(),
+            dashboards: content_pack
+                .dashboards
+                .iter()
+                .map(|dashboard| DashboardReport::new(dashboard, backend))
+                .collect(),
+        }
+    }
+
+    /// Number of widgets with each fidelity, in the order exact, approximate, skipped.
+    pub fn totals(&self) -> (usize, usize, usize) {
+        self.dashboards
+            .iter()
+            .flat_map(|d| &d.widgets)
+            .fold((0, 0, 0), |(e, a, s), w| match w.fidelity {
+                Fidelity::Exact => (e + 1, a, s),
+                Fidelity::Approximate => (e, a + 1, s),
+                Fidelity::Skipped => (e, a, s + 1),
+            })
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let (exact, approximate, skipped) = self.totals();
+        let mut markdown = format!(
+            "# Conversion of {}\n\n{} exact, {} approximate, {} skipped\n",
+            self.content_pack, exact, approximate, skipped
+        );
+        for dashboard in &self.dashboards {
+            markdown += &format!(
+                "\n## {}\n\n| Widget | Source | Target | Fidelity | Notes |\n|---|---|---|---|---|\n",
+                dashboard.title
+            );
+            for widget in &dashboard.widgets {
+                let row = [
+                    widget.title.as_str(),
+                    &widget.source_type,
+                    widget.target_type.as_deref().unwrap_or("-"),
+                    fidelity_name(widget.fidelity),
+                    &widget.notes().join("; "),
+                ]
+                .iter()
+                .map(|cell| cell.replace('|', "\\|"))
+                .collect::<Vec<_>>()
+                .join(" | ");
+                markdown += &format!("| {} |\n", row);
+            }
+        }
+        markdown
+    }
+
+    pub fn to_html(&self) -> String {
+        let (exact, approximate, skipped) = self.totals();
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Conversion of {0}</title>\n\
+             <style>table {{ border-collapse: collapse; }} td, th {{ border: 1px solid #ccc; padding: 4px 8px; }} \
+             .approximate {{ background: #fff3cd; }} .skipped {{ background: #f8d7da; }}</style>\n\
+             </head>\n<body>\n<h1>Conversion of {0}</h1>\n<p>{1} exact, {2} approximate, {3} skipped</p>\n",
+            escape(&self.content_pack),
+            exact,
+            approximate,
+            skipped
+        );
+        for dashboard in &self.dashboards {
+            html += &format!(
+                "<h2>{}</h2>\n<table>\n<tr><th>Widget</th><th>Source</th><th>Target</th><th>Fidelity</th><th>Notes</th></tr>\n",
+                escape(&dashboard.title)
+            );
+            for widget in &dashboard.widgets {
+                let fidelity = fidelity_name(widget.fidelity);
+                let notes: Vec<String> = widget.notes().iter().map(|n| escape(n)).collect();
+                html += &format!(
+                    "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    fidelity,
+                    escape(&widget.title),
+                    escape(&widget.source_type),
+                    escape(widget.target_type.as_deref().unwrap_or("-")),
+                    fidelity,
+                    notes.join("<br>")
+                );
+            }
+            html += "</table>\n";
+        }
+        html + "</body>\n</html>\n"
+    }
+
+    pub fn write(&self, path: &Path, format: Format) -> Result<(), Box<dyn Error>> {
+        let content = match format {
+            Format::Json => serde_json::to_string_pretty(self)?,
+            Format::Markdown => self.to_markdown(),
+            Format::Html => self.to_html(),
+        };
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+impl DashboardReport {
+    pub fn new(dashboard: &ir::Dashboard, backend: Backend) -> DashboardReport {
+        let mut widgets: Vec<WidgetReport> = dashboard
+            .panels
+            .iter()
+            .map(|panel| {
+                let warnings: Vec<String> = panel
+                    .warnings
+                    .iter()
+                    .map(|warning| warning.message.clone())
+                    .chain(backend.deviations(panel))
+                    .collect();
+                WidgetReport {
+                    title: panel.title.clone(),
+                    source_type: panel.source_type.clone().unwrap_or_default(),
+                    target_type: Some(backend.target_type(&panel.visualization)),
+                    fidelity: if warnings.is_empty() {
+                        Fidelity::Exact
+                    } else {
+                        Fidelity::Approximate
+                    },
+                    reasons: vec![],
+                    warnings,
+                }
+            })
+            .collect();
+        widgets.extend(dashboard.skipped.iter().map(|skipped| WidgetReport {
+            title: skipped.title.clone(),
+            source_type: skipped.source_type.clone(),
+            target_type: None,
+            fidelity: Fidelity::Skipped,
+            reasons: vec![skipped.reason.clone()],
+            warnings: vec![],
+        }));
+        DashboardReport {
+            title: dashboard.title.clone(),
+            widgets,
+        }
+    }
+}
+
+impl WidgetReport {
+    fn notes(&self) -> Vec<String> {
+        self.reasons.iter().chain(&self.warnings).cloned().collect()
+    }
+}
+
+fn fidelity_name(fidelity: Fidelity) -> &'static str {
+    match fidelity {
+        Fidelity::Exact => "exact",
+        Fidelity::Approximate => "approximate",
+        Fidelity::Skipped => "skipped",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::fixtures::{approximated_panel, skipped_map};
+    use crate::ir::{ChartStyle, Query, Visualization};
+
+    fn content_pack() -> ir::ContentPack {
+        let mut dashboard = ir::Dashboard::new("Web")
+            .with_panel(
+                ir::Panel::new("Errors", Visualization::SingleValue { trend: false })
+                    .with_query(Query::new_count("level:3"))
+                    .with_time_range(3600),
+            )
+            .with_panel(approximated_panel(
+                "Latency",
+                Visualization::Chart {
+                    style: ChartStyle::Line,
+                },
+            ));
+        dashboard.skipped.push(skipped_map("Map <world>"));
+        ir::ContentPack {
+            name: "web".to_string(),
+            dashboards: vec![dashboard],
+        }
+    }
+
+    #[test]
+    fn fidelity_per_widget() {
+        let report = Report::new(&content_pack(), Backend::Grafana);
+
+        let widgets = &report.dashboards[0].widgets;
+        assert_eq!(Fidelity::Exact, widgets[0].fidelity);
+        assert_eq!(Some("singlestat".to_string()), widgets[0].target_type);
+        assert_eq!(Fidelity::Approximate, widgets[1].fidelity);
+        assert_eq!(
+            vec!["std_deviation approximated as sum"],
+            widgets[1].warnings
+        );
+        assert_eq!(Fidelity::Skipped, widgets[2].fidelity);
+        assert_eq!(None, widgets[2].target_type);
+        assert_eq!((1, 1, 1), report.totals());
+    }
+
+    #[test]
+    fn markdown_and_html_list_every_widget() {
+        let report = Report::new(&content_pack(), Backend::Grafana);
+
+        let markdown = report.to_markdown();
+        let html = report.to_html();
+
+        assert!(markdown
+            .contains("| Latency |  | graph | approximate | std_deviation approximated as sum |"));
+        assert!(markdown.contains("| Map <world> | ORG_MAP | - | skipped |"));
+        assert!(html.contains("<td>Map &lt;world&gt;</td>"));
+        assert!(html.contains("<tr class=\"skipped\">"));
+    }
+
+    #[test]
+    fn targets_of_other_backends() {
+        let perses = Report::new(&content_pack(), Backend::Perses);
+        let kibana = Report::new(&content_pack(), Backend::SavedObjects);
+
+        let widgets = &perses.dashboards[0].widgets;
+        assert_eq!(Some("StatChart".to_string()), widgets[0].target_type);
+        assert_eq!(
+            vec![
+                "time range of 3600s replaced by the dashboard time range",
+                "queries use a placeholder plugin"
+            ],
+            widgets[0].warnings
+        );
+        let widgets = &kibana.dashboards[0].widgets;
+        assert_eq!(Some("line".to_string()), widgets[1].target_type);
+        assert_eq!(Fidelity::Approximate, widgets[0].fidelity);
+        assert_eq!((0, 2, 1), kibana.totals());
+    }
+}
@@ -0,0 +1,473 @@
+//! Reverse conversion of Grafana dashboards into a Graylog content pack.
+//!
+//! Grafana dashboard JSON is parsed into the intermediate representation in [`crate::ir`],
+//! which is written as widgets of a Graylog 2.x content pack. Panels without a Graylog
+//! counterpart are skipped and reported as [`Skipped`].
+
+use crate::ir::{
+    Bucket, ChartStyle, ContentPack, Dashboard, Layout, MetricFunction, Panel, Query, Visualization,
+};
+use crate::widgets::{
+    FIELD_CHART, QUICKVALUES, SEARCH_RESULT_CHART, SEARCH_RESULT_COUNT, STACKED_CHART,
+};
+use serde_json::{json, Value};
+
+/// Field charts need a field even when counting messages, every message has a timestamp.
+const COUNT_FIELD: &str = "timestamp";
+/// Graylog widget showing a statistic of a message field as single value.
+const STATS_COUNT: &str = "STATS_COUNT";
+/// Grafana's default time range, for dashboards whose range isn't relative.
+const DEFAULT_RANGE: i64 = 6 * 3600;
+
+/// Panel left out of the content pack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub dashboard: String,
+    pub panel: String,
+    pub reason: String,
+}
+
+/// Parses Grafana dashboards, as saved by Grafana, exported for sharing or returned by
+/// `/api/dashboards/uid`, into the intermediate representation.
+pub fn parse(name: &str, dashboards: &[Value]) -> (ContentPack, Vec<Skipped>) {
+    let mut skipped = vec![];
+    let dashboards = dashboards
+        .iter()
+        .map(|json| {
+            let (dashboard, mut s) = parse_dashboard(json.get("dashboard").unwrap_or(json));
+            skipped.append(&mut s);
+            dashboard
+        })
+        .collect();
+    let content_pack = ContentPack {
+        name: name.to_string(),
+        dashboards,
+    };
+    (content_pack, skipped)
+}
+
+fn parse_dashboard(json: &Value) -> (Dashboard, Vec<Skipped>) {
+    let mut dashboard = Dashboard::new(json["title"].as_str().unwrap_or_default());
+    dashboard.id = json["uid"].as_str().map(str::to_string);
+    dashboard.description = json["description"].as_str().unwrap_or_default().to_string();
+    let range = json["time"]["from"]
+        .as_str()
+        .and_then(seconds)
+        .unwrap_or(DEFAULT_RANGE);
+    let mut skipped = vec![];
+    for panel in panels(json) {
+        match parse_panel(panel, range) {
+            Ok(parsed) => dashboard.panels.push(parsed),
+            Err(reason) => skipped.push(Skipped {
+                dashboard: dashboard.title.clone(),
+                panel: panel["title"].as_str().unwrap_or_default().to_string(),
+                reason,
+            }),
+        }
+    }
+    (dashboard, skipped)
+}
+
+/// All panels of a dashboard, including the ones inside collapsed rows.
+fn panels(json: &Value) -> Vec<&Value> {
+    let mut panels = vec![];
+    for panel in json["panels"].as_array().into_iter().flatten() {
+        if panel["type"] == "row" {
+            panels.extend(panel["panels"].as_array().into_iter().flatten());
+        } else {
+            panels.push(panel);
+        }
+    }
+    panels
+}
+
+fn parse_panel(panel: &Value, dashboard_range: i64) -> Result<Panel, String> {
+    let r#type = panel["type"].as_str().unwrap_or_default();
+    let visualization = match r#type {
+        "singlestat" => Visualization::SingleValue {
+            trend: panel["sparkline"]["show"].as_bool().unwrap_or(false),
+        },
+        "stat" => Visualization::SingleValue {
+            trend: panel["options"]["graphMode"] == "area",
+        },
+        "graph" => {
+            let style = if panel["bars"].as_bool().unwrap_or(false) {
+                ChartStyle::Bar
+            } else if panel["points"].as_bool().unwrap_or(false) {
+                ChartStyle::Area
+            } else {
+                ChartStyle::Line
+            };
+            Visualization::Chart { style }
+        }
+        "timeseries" => {
+            let custom = &panel["fieldConfig"]["defaults"]["custom"];
+            let style = if custom["drawStyle"] == "bars" {
+                ChartStyle::Bar
+            } else if custom["fillOpacity"].as_f64().unwrap_or(0.0) > 0.0 {
+                ChartStyle::Area
+            } else {
+                ChartStyle::Line
+            };
+            Visualization::Chart { style }
+        }
+        "piechart" | "grafana-piechart-panel" => Visualization::Pie,
+        _ => return Err(format!("{} panels have no Graylog equivalent", r#type)),
+    };
+    let datasource = panel["datasource"]["type"].as_str();
+    if let Some(other) = datasource.filter(|t| *t != "elasticsearch") {
+        return Err(format!("{} datasources aren't supported", other));
+    }
+    let targets: Vec<&Value> = panel["targets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|target| !target["hide"].as_bool().unwrap_or(false))
+        .collect();
+    if targets.is_empty() {
+        return Err("no queries".to_string());
+    }
+    let range = panel["timeFrom"]
+        .as_str()
+        .and_then(seconds)
+        .unwrap_or(dashboard_range);
+    let mut parsed = Panel::new(panel["title"].as_str().unwrap_or_default(), visualization)
+        .with_time_range(range)
+        .with_layout(layout(&panel["gridPos"]));
+    parsed.source_type = Some(r#type.to_string());
+    for target in targets {
+        parsed = parsed.with_query(parse_target(target)?);
+    }
+    Ok(parsed)
+}
+
+fn parse_target(target: &Value) -> Result<Query, String> {
+    let metrics = target["metrics"]
+        .as_array()
+        .ok_or("queries aren't Elasticsearch queries")?;
+    let query = match target["query"].as_str().unwrap_or_default() {
+        "" => "*",
+        query => query,
+    };
+    let metric = metrics.first().ok_or("queries without metric")?;
+    let function = match metric["type"].as_str().unwrap_or_default() {
+        "count" => MetricFunction::Count,
+        "sum" => MetricFunction::Sum,
+        "avg" => MetricFunction::Avg,
+        "min" => MetricFunction::Min,
+        "max" => MetricFunction::Max,
+        "cardinality" => MetricFunction::Cardinality,
+        other => return Err(format!("{} metrics have no Graylog equivalent", other)),
+    };
+    let field = match (function, metric["field"].as_str()) {
+        (MetricFunction::Count, _) | (_, None) | (_, Some("select field")) => None,
+        (_, Some(field)) => Some(field.to_string()),
+    };
+    let mut parsed = Query::new_count(query).with_metric(function, field);
+    for agg in target["bucketAggs"].as_array().into_iter().flatten() {
+        let settings = &agg["settings"];
+        let bucket = match agg["type"].as_str().unwrap_or_default() {
+            "date_histogram" => Bucket::DateHistogram {
+                interval: settings["interval"].as_str().unwrap_or("auto").to_string(),
+            },
+            "terms" => Bucket::Terms {
+                field: agg["field"].as_str().unwrap_or_default().to_string(),
+                order: settings["order"].as_str().unwrap_or("desc").to_string(),
+                size: number(&settings["size"]).unwrap_or(0),
+            },
+            other => return Err(format!("{} buckets have no Graylog equivalent", other)),
+        };
+        parsed = parsed.with_bucket(bucket);
+    }
+    Ok(parsed)
+}
+
+/// Grafana numbers settings like the terms size as strings.
+fn number(value: &Value) -> Option<i64> {
+    value
+        .as_i64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+/// Seconds of a relative time like `now-6h` or `30m`.
+fn seconds(time: &str) -> Option<i64> {
+    let duration = time.trim_start_matches("now-");
+    let unit = duration.chars().last()?;
+    let value: i64 = duration[..duration.len() - unit.len_utf8()].parse().ok()?;
+    let factor = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 7 * 86400,
+        'M' => 30 * 86400,
+        'y' => 365 * 86400,
+        _ => return None,
+    };
+    Some(value * factor)
+}
+
+/// Inverse of `grafana::GridPos::new`, Graylog cells are 5 Grafana columns wide and 6 rows high.
+fn layout(grid_pos: &Value) -> Layout {
+    let cells = |key: &str, size: i64| (grid_pos[key].as_i64().unwrap_or(0) + size - 1) / size;
+    Layout {
+        row: (grid_pos["y"].as_i64().unwrap_or(0) / 6).max(1),
+        col: grid_pos["x"].as_i64().unwrap_or(0) / 5 + 1,
+        width: cells("w", 5).max(1),
+        height: cells("h", 6).max(1),
+    }
+}
+
+/// Graylog 2.x content pack with the dashboards of `content_pack`. Panels which can't be
+/// written as widget are skipped.
+pub fn content_pack(content_pack: &ContentPack) -> (Value, Vec<Skipped>) {
+    let mut skipped = vec![];
+    let mut dashboards = vec![];
+    for dashboard in &content_pack.dashboards {
+        let mut widgets = vec![];
+        for panel in &dashboard.panels {
+            match widget(panel) {
+                Ok(widget) => widgets.push(widget),
+                Err(reason) => skipped.push(Skipped {
+                    dashboard: dashboard.title.clone(),
+                    panel: panel.title.clone(),
+                    reason,
+                }),
+            }
+        }
+        dashboards.push(json!({
+            "title": dashboard.title,
+            "description": dashboard.description,
+            "dashboard_widgets": widgets,
+        }));
+    }
+    let pack = json!({
+        "name": content_pack.name,
+        "description": "Converted from Grafana dashboards",
+        "category": "Grafana",
+        "inputs": [],
+        "streams": [],
+        "outputs": [],
+        "dashboards": dashboards,
+        "grok_patterns": [],
+        "lookup_tables": [],
+        "lookup_caches": [],
+        "lookup_data_adapters": [],
+    });
+    (pack, skipped)
+}
+
+/// Closest Graylog widget: counts, charts over time or quick values.
+fn widget(panel: &Panel) -> Result<Value, String> {
+    let query = panel.queries.first().ok_or("no queries")?;
+    let range = panel.time_range.unwrap_or(DEFAULT_RANGE);
+    let mut configuration = json!({
+        "timerange": {"type": "relative", "range": range},
+        "query": query.query,
+    });
+    let r#type = match panel.visualization {
+        Visualization::SingleValue { trend } => {
+            configuration["trend"] = json!(trend);
+            configuration["lower_is_better"] = json!(false);
+            match (&query.metric.field, query.metric.function) {
+                (Some(field), function) if function != MetricFunction::Count => {
+                    configuration["field"] = json!(field);
+                    configuration["stats_function"] = json!(valuetype(function));
+                    STATS_COUNT
+                }
+                _ => SEARCH_RESULT_COUNT,
+            }
+        }
+        Visualization::Chart { style } => {
+            if query
+                .buckets
+                .iter()
+                .any(|b| matches!(b, Bucket::Terms { .. }))
+            {
+                return Err("charts split by terms have no Graylog equivalent".to_string());
+            }
+            configuration["interval"] = json!(interval(query));
+            let renderer = match style {
+                ChartStyle::Bar => "bar",
+                ChartStyle::Line => "line",
+                ChartStyle::Area => "area",
+            };
+            if panel.queries.len() > 1 {
+                let series: Vec<Value> = panel
+                    .queries
+                    .iter()
+                    .map(|q| {
+                        json!({
+                            "query": q.query,
+                            "field": q.metric.field.as_deref().unwrap_or(COUNT_FIELD),
+                            "statistical_function": valuetype(q.metric.function),
+                        })
+                    })
+                    .collect();
+                configuration["series"] = json!(series);
+                configuration["renderer"] = json!(renderer);
+                configuration["interpolation"] = json!("linear");
+                STACKED_CHART
+            } else if query.metric.function == MetricFunction::Count && style == ChartStyle::Bar {
+                SEARCH_RESULT_CHART
+            } else {
+                configuration["field"] =
+                    json!(query.metric.field.as_deref().unwrap_or(COUNT_FIELD));
+                configuration["valuetype"] = json!(valuetype(query.metric.function));
+                configuration["renderer"] = json!(renderer);
+                configuration["interpolation"] = json!("linear");
+                FIELD_CHART
+            }
+        }
+        Visualization::Pie => {
+            let (field, order, size) = query
+                .buckets
+                .iter()
+                .find_map(|b| match b {
+                    Bucket::Terms { field, order, size } => Some((field, order, *size)),
+                    _ => None,
+                })
+                .ok_or("pie charts without terms have no Graylog equivalent")?;
+            configuration["field"] = json!(field);
+            configuration["sort_order"] = json!(order);
+            configuration["limit"] = json!(if size > 0 { size } else { 5 });
+            configuration["show_pie_chart"] = json!(true);
+            configuration["show_data_table"] = json!(false);
+            QUICKVALUES
+        }
+    };
+    Ok(json!({
+        "description": panel.title,
+        "type": r#type,
+        "cache_time": 10,
+        "configuration": configuration,
+        "row": panel.layout.row,
+        "col": panel.layout.col,
+        "width": panel.layout.width,
+        "height": panel.layout.height,
+    }))
+}
+
+/// Graylog statistical function computing a metric.
+fn valuetype(function: MetricFunction) -> &'static str {
+    match function {
+        MetricFunction::Count => "count",
+        MetricFunction::Sum => "total",
+        MetricFunction::Avg => "mean",
+        MetricFunction::Min => "min",
+        MetricFunction::Max => "max",
+        MetricFunction::Cardinality => "cardinality",
+    }
+}
+
+/// Graylog charts only know calendar intervals, others are rounded down to the next one.
+fn interval(query: &Query) -> &'static str {
+    let seconds = query
+        .buckets
+        .iter()
+        .find_map(|b| match b {
+            Bucket::DateHistogram { interval } => self::seconds(interval),
+            _ => None,
+        })
+        .unwrap_or(3600);
+    match seconds {
+        s if s < 3600 => "minute",
+        s if s < 86400 => "hour",
+        s if s < 7 * 86400 => "day",
+        _ => "week",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{graylog, parse as parse_graylog};
+
+    #[test]
+    fn grafana_panels_become_closest_widgets() {
+        let dashboard = json!({"dashboard": {
+            "uid": "web",
+            "title": "Web",
+            "time": {"from": "now-12h", "to": "now"},
+            "panels": [
+                {
+                    "type": "stat", "title": "Errors", "options": {"graphMode": "area"},
+                    "gridPos": {"x": 0, "y": 0, "w": 6, "h": 4},
+                    "targets": [{"query": "level:3", "metrics": [{"type": "count", "id": "1"}],
+                        "bucketAggs": [{"type": "date_histogram", "settings": {"interval": "auto"}}]}],
+                },
+                {"type": "row", "title": "More", "collapsed": true, "panels": [{
+                    "type": "timeseries", "title": "Latency", "timeFrom": "1h",
+                    "fieldConfig": {"defaults": {"custom": {"drawStyle": "line"}}},
+                    "gridPos": {"x": 12, "y": 8, "w": 12, "h": 8},
+                    "targets": [{"query": "", "metrics": [{"type": "avg", "id": "1", "field": "took_ms"}],
+                        "bucketAggs": [{"type": "date_histogram", "settings": {"interval": "5m"}}]}],
+                }]},
+                {"type": "table", "title": "Messages", "targets": []},
+                {
+                    "type": "piechart", "title": "Sources",
+                    "datasource": {"type": "prometheus", "uid": "p1"},
+                    "targets": [{"expr": "up"}],
+                },
+            ],
+        }});
+
+        let (parsed, mut skipped) = parse("grafana", &[dashboard]);
+        let (pack, mut more) = content_pack(&parsed);
+        skipped.append(&mut more);
+
+        assert_eq!(
+            vec!["Messages", "Sources"],
+            skipped.iter().map(|s| s.panel.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!("table panels have no Graylog equivalent", skipped[0].reason);
+        let widgets = &pack["dashboards"][0]["dashboard_widgets"];
+        assert_eq!(SEARCH_RESULT_COUNT, widgets[0]["type"]);
+        assert_eq!(true, widgets[0]["configuration"]["trend"]);
+        assert_eq!(43200, widgets[0]["configuration"]["timerange"]["range"]);
+        assert_eq!(FIELD_CHART, widgets[1]["type"]);
+        assert_eq!("mean", widgets[1]["configuration"]["valuetype"]);
+        assert_eq!("minute", widgets[1]["configuration"]["interval"]);
+        assert_eq!("*", widgets[1]["configuration"]["query"]);
+        assert_eq!(3600, widgets[1]["configuration"]["timerange"]["range"]);
+        assert_eq!(
+            (1, 3, 3),
+            (
+                widgets[1]["row"].as_i64().unwrap(),
+                widgets[1]["col"].as_i64().unwrap(),
+                widgets[1]["width"].as_i64().unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn content_pack_reads_back() {
+        let pack = ContentPack {
+            name: "grafana".to_string(),
+            dashboards: vec![Dashboard::new("Web").with_panel(
+                Panel::new("Levels", Visualization::Pie)
+                    .with_query(Query::new_count("source:web").with_bucket(Bucket::Terms {
+                        field: "level".to_string(),
+                        order: "asc".to_string(),
+                        size: 10,
+                    }))
+                    .with_time_range(300),
+            )],
+        };
+        let mut with_empty = pack.clone();
+        with_empty.dashboards[0]
+            .panels
+            .push(Panel::new("Empty", Visualization::Pie));
+
+        let (json, skipped) = content_pack(&with_empty);
+        let read: graylog::ContentPack = serde_json::from_value(json).unwrap();
+        let mut parsed = parse_graylog(read).unwrap();
+
+        assert_eq!(1, skipped.len());
+        assert_eq!("no queries", skipped[0].reason);
+        let panel = &mut parsed.dashboards[0].panels[0];
+        assert_eq!(Some(QUICKVALUES), panel.source_type.as_deref());
+        panel.source_type = None;
+        assert_eq!(pack, parsed);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::grafana::{Dashboard, GridPos};
+use crate::{ir, ConversionOptions};
+use log::warn;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// File receiving the saved objects, importable by the saved objects API of Kibana
+/// and OpenSearch Dashboards.
+pub const SAVED_OBJECTS_FILE: &str = "saved_objects.ndjson";
+/// Version written into the panels, the last one OpenSearch Dashboards is compatible with.
+const KIBANA_VERSION: &str = "7.10.2";
+const INDEX_REF: &str = "kibanaSavedObjectMeta.searchSourceJSON.index";
+
+/// Index pattern of the Graylog indices all visualizations search.
+#[derive(Debug, Clone)]
+pub struct IndexPattern {
+    pub id: String,
+    pub title: String,
+    pub time_field: String,
+}
+
+impl IndexPattern {
+    pub fn new(title: &str, time_field: &str) -> IndexPattern {
+        IndexPattern {
+            id: "graylog".to_string(),
+            title: title.to_string(),
+            time_field: time_field.to_string(),
+        }
+    }
+
+    pub fn saved_object(&self) -> Value {
+        json!({
+            "type": "index-pattern",
+            "id": self.id,
+            "attributes": {"title": self.title, "timeFieldName": self.time_field},
+            "references": [],
+        })
+    }
+}
+
+/// Saved objects of a dashboard of the intermediate representation, with the settings
+/// `opt` has for it: a visualization per panel followed by the dashboard.
+pub fn saved_objects(
+    dash: &ir::Dashboard,
+    opt: &ConversionOptions,
+    index: &IndexPattern,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    let uid = Dashboard::uid_from_title(&dash.title);
+    let mut objects = vec![];
+    let mut panels = vec![];
+    let mut references = vec![];
+    for (i, panel) in dash.panels.iter().enumerate() {
+        let id = format!("{}-panel-{}", uid, i + 1);
+        objects.push(visualization(&id, panel, index)?);
+        let panel_index = (i + 1).to_string();
+        // Kibana's grid has 48 columns of 20px high rows, Grafana's 24 columns of 30px.
+        let grid_pos = GridPos::new_with_layout(&panel.layout);
+        panels.push(json!({
+            "panelIndex": panel_index,
+            "gridData": {
+                "x": grid_pos.x * 2,
+                "y": grid_pos.y * 3 / 2,
+                "w": grid_pos.w * 2,
+                "h": grid_pos.h * 3 / 2,
+                "i": panel_index,
+            },
+            "version": KIBANA_VERSION,
+            "panelRefName": format!("panel_{}", i),
+            "embeddableConfig": {},
+        }));
+        references.push(json!({"name": format!("panel_{}", i), "type": "visualization", "id": id}));
+    }
+    let time = opt.settings_for(dash).time;
+    let (from, to) = time.map_or(("now-2d".to_string(), "now".to_string()), |t| {
+        (t.from, t.to)
+    });
+    objects.push(json!({
+        "type": "dashboard",
+        "id": uid,
+        "attributes": {
+            "title": dash.title,
+            "description": dash.description,
+            "panelsJSON": serde_json::to_string(&panels)?,
+            "optionsJSON": serde_json::to_string(&json!({"useMargins": true, "hidePanelTitles": false}))?,
+            "timeRestore": true,
+            "timeFrom": from,
+            "timeTo": to,
+            "kibanaSavedObjectMeta": {
+                "searchSourceJSON": serde_json::to_string(&search_source(""))?,
+            },
+        },
+        "references": references,
+    }));
+    Ok(objects)
+}
+
+/// Kibana visualization type showing `visualization`.
+pub fn visualization_type(visualization: &ir::Visualization) -> &'static str {
+    match visualization {
+        ir::Visualization::SingleValue { .. } => "metric",
+        ir::Visualization::Chart { style } => match style {
+            ir::ChartStyle::Bar => "histogram",
+            ir::ChartStyle::Line => "line",
+            ir::ChartStyle::Area => "area",
+        },
+        ir::Visualization::Pie => "pie",
+    }
+}
+
+/// Metric, histogram, line or area and pie visualizations for single values, charts and pies.
+fn visualization(
+    id: &str,
+    panel: &ir::Panel,
+    index: &IndexPattern,
+) -> Result<Value, Box<dyn Error>> {
+    if panel.queries.len() > 1 {
+        warn!(
+            "Not Supported multiple queries in visualization \"{}\", only the first is kept",
+            panel.title
+        );
+    }
+    let query = panel.queries.first();
+    let mut aggs = vec![metric_agg(query)];
+    let (r#type, params) = match panel.visualization {
+        ir::Visualization::SingleValue { .. } => (
+            visualization_type(&panel.visualization),
+            json!({"addTooltip": true, "addLegend": false}),
+        ),
+        ir::Visualization::Chart { .. } => {
+            aggs.push(json!({
+                "id": "2",
+                "enabled": true,
+                "type": "date_histogram",
+                "schema": "segment",
+                "params": {"field": index.time_field, "interval": "auto", "min_doc_count": 1},
+            }));
+            (
+                visualization_type(&panel.visualization),
+                json!({"addTooltip": true, "addLegend": true, "legendPosition": "right"}),
+            )
+        }
+        ir::Visualization::Pie => {
+            aggs.push(terms_agg(query));
+            (
+                visualization_type(&panel.visualization),
+                json!({"addTooltip": true, "addLegend": true, "isDonut": false}),
+            )
+        }
+    };
+    let vis_state = json!({"title": panel.title, "type": r#type, "params": params, "aggs": aggs});
+    let mut search_source = search_source(query.map_or("", |query| query.query.as_str()));
+    search_source["indexRefName"] = json!(INDEX_REF);
+    Ok(json!({
+        "type": "visualization",
+        "id": id,
+        "attributes": {
+            "title": panel.title,
+            "description": "",
+            "visState": serde_json::to_string(&vis_state)?,
+            "uiStateJSON": "{}",
+            "version": 1,
+            "kibanaSavedObjectMeta": {"searchSourceJSON": serde_json::to_string(&search_source)?},
+        },
+        "references": [{"name": INDEX_REF, "type": "index-pattern", "id": index.id}],
+    }))
+}
+
+fn search_source(query: &str) -> Value {
+    json!({"query": {"query": query, "language": "lucene"}, "filter": []})
+}
+
+/// Metric of the query, Kibana knows the Elasticsearch aggregations by the same names.
+fn metric_agg(query: Option<&ir::Query>) -> Value {
+    match query.map(|query| &query.metric) {
+        Some(ir::Metric {
+            function,
+            field: Some(field),
+        }) if *function != ir::MetricFunction::Count => json!({
+            "id": "1",
+            "enabled": true,
+            "type": function.aggregation(),
+            "schema": "metric",
+            "params": {"field": field},
+        }),
+        _ => json!({"id": "1", "enabled": true, "type": "count", "schema": "metric", "params": {}}),
+    }
+}
+
+fn terms_agg(query: Option<&ir::Query>) -> Value {
+    let terms = query.and_then(|query| {
+        query.buckets.iter().find_map(|bucket| match bucket {
+            ir::Bucket::Terms { field, order, size } => {
+                Some((field.as_str(), order.as_str(), *size))
+            }
+            _ => None,
+        })
+    });
+    let (field, order, size) = terms.unwrap_or(("", "desc", 0));
+    json!({
+        "id": "2",
+        "enabled": true,
+        "type": "terms",
+        "schema": "segment",
+        "params": {
+            "field": field,
+            // Kibana needs a limit where Graylog may have none.
+            "size": if size > 0 { size } else { 10 },
+            "order": order,
+            "orderBy": "1",
+        },
+    })
+}
+
+/// Writes the index pattern and the saved objects of all dashboards of `content_pack`
+/// as NDJSON into `dir`.
+pub fn write(
+    dir: &Path,
+    content_pack: &ir::ContentPack,
+    opt: &ConversionOptions,
+    index: &IndexPattern,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = File::create(dir.join(SAVED_OBJECTS_FILE))?;
+    writeln!(file, "{}", index.saved_object())?;
+    for dash in &content_pack.dashboards {
+        for object in saved_objects(dash, opt, index)? {
+            writeln!(file, "{}", object)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pie_chart_becomes_terms_visualization() {
+        let dash = ir::Dashboard::new("Web").with_panel(
+            ir::Panel::new("Levels", ir::Visualization::Pie)
+                .with_query(
+                    ir::Query::new_count("source:web").with_bucket(ir::Bucket::Terms {
+                        field: "level".to_string(),
+                        order: "asc".to_string(),
+                        size: 0,
+                    }),
+                )
+                .with_layout(ir::Layout {
+                    row: 1,
+                    col: 2,
+                    width: 2,
+                    height: 1,
+                }),
+        );
+        let index = IndexPattern::new("graylog_*", "timestamp");
+
+        let objects = saved_objects(&dash, &ConversionOptions::default(), &index).unwrap();
+
+        assert_eq!(2, objects.len());
+        let vis_state: Value =
+            serde_json::from_str(objects[0]["attributes"]["visState"].as_str().unwrap()).unwrap();
+        assert_eq!("pie", vis_state["type"]);
+        assert_eq!("level", vis_state["aggs"][1]["params"]["field"]);
+        assert_eq!(10, vis_state["aggs"][1]["params"]["size"]);
+        assert_eq!("asc", vis_state["aggs"][1]["params"]["order"]);
+        assert_eq!("graylog", objects[0]["references"][0]["id"]);
+
+        let dashboard_object = &objects[1];
+        assert_eq!(
+            "web-29751047-panel-1",
+            dashboard_object["references"][0]["id"]
+        );
+        let panels: Value = serde_json::from_str(
+            dashboard_object["attributes"]["panelsJSON"]
+                .as_str()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(20, panels[0]["gridData"]["w"]);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::backup::Backup;
+use crate::client::GrafanaClient;
+use crate::grafana;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind};
+use std::path::Path;
+
+/// Remembers which Graylog dashboard became which Grafana dashboard.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct State {
+    /// Keyed by the title of the Graylog dashboard.
+    pub dashboards: BTreeMap<String, DashboardState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DashboardState {
+    pub uid: String,
+    pub version: i64,
+    pub folder_id: i64,
+    pub hash: String,
+}
+
+impl State {
+    /// Reads the state file, a missing file is treated as an empty state.
+    pub fn load(path: &Path) -> Result<State, Box<dyn Error>> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Create(grafana::Dashboard),
+    Update {
+        dashboard: grafana::Dashboard,
+        previous: DashboardState,
+    },
+    /// Grafana holds a different version than the one we wrote last.
+    Drift {
+        dashboard: grafana::Dashboard,
+        previous: DashboardState,
+        version: i64,
+    },
+    Unchanged(String),
+    Delete {
+        title: String,
+        previous: DashboardState,
+    },
+}
+
+#[derive(Debug)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+}
+
+impl Plan {
+    /// Computes the actions needed to bring Grafana in line with `dashboards`.
+    /// `remote_version` returns the version Grafana currently has for a uid.
+    pub fn new<F>(
+        dashboards: Vec<grafana::Dashboard>,
+        state: &State,
+        remote_version: F,
+    ) -> Result<Plan, Box<dyn Error>>
+    where
+        F: Fn(&str) -> Result<Option<i64>, Box<dyn Error>>,
+    {
+        let mut actions = vec![];
+        for dashboard in dashboards {
+            let previous = match state.dashboards.get(&dashboard.title) {
+                Some(previous) => previous.clone(),
+                None => {
+                    actions.push(Action::Create(dashboard));
+                    continue;
+                }
+            };
+            let action = match remote_version(&previous.uid)? {
+                None => Action::Create(dashboard),
+                Some(version) if version != previous.version => Action::Drift {
+                    dashboard,
+                    previous,
+                    version,
+                },
+                Some(_) => {
+                    if previous.hash == dashboard.content_hash()
+                        && previous.folder_id == dashboard.folder_id
+                    {
+                        Action::Unchanged(dashboard.title)
+                    } else {
+                        Action::Update {
+                            dashboard,
+                            previous,
+                        }
+                    }
+                }
+            };
+            actions.push(action);
+        }
+
+        for (title, previous) in &state.dashboards {
+            let still_present = actions.iter().any(|a| a.title() == title);
+            if !still_present {
+                actions.push(Action::Delete {
+                    title: title.clone(),
+                    previous: previous.clone(),
+                });
+            }
+        }
+        Ok(Plan { actions })
+    }
+
+    /// Keeps the dashboards titled `titles` in Grafana, e.g. ones which failed to convert
+    /// but still exist in Graylog.
+    pub fn keep(mut self, titles: &[String]) -> Plan {
+        self.actions.retain(|a| match a {
+            Action::Delete { title, .. } => !titles.contains(title),
+            _ => true,
+        });
+        self
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.actions
+            .iter()
+            .any(|a| !matches!(a, Action::Unchanged(_)))
+    }
+
+    /// Executes the plan and records the outcome in `state`, which is saved to `state_path`
+    /// after every action. Drifted dashboards are only overwritten with `force`.
+    pub fn apply(
+        self,
+        client: &GrafanaClient,
+        state: &mut State,
+        state_path: &Path,
+        backup: Option<&Backup>,
+        force: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        for action in self.actions {
+            match action {
+                Action::Unchanged(_) => continue,
+                Action::Drift {
+                    ref dashboard,
+                    version,
+                    ..
+                } if !force => {
+                    warn!(
+                        "Skipping \"{}\": Grafana has version {}, use --force to overwrite",
+                        dashboard.title, version
+                    );
+                    continue;
+                }
+                Action::Create(dashboard)
+                | Action::Update { dashboard, .. }
+                | Action::Drift { dashboard, .. } => {
+                    if let Some(backup) = backup {
+                        if let Some(existing) =
+                            client.find_dashboard(&dashboard, dashboard.folder_id)?
+                        {
+                            backup.save(&existing)?;
+                        }
+                    }
+                    let hash = dashboard.content_hash();
+                    let title = dashboard.title.clone();
+                    let folder_id = dashboard.folder_id;
+                    let response = client.post_dashboard(&grafana::ApiDashboard {
+                        dashboard,
+                        folder_id,
+                        overwrite: true,
+                    })?;
+                    info!("Wrote dashboard \"{}\"", title);
+                    state.dashboards.insert(
+                        title,
+                        DashboardState {
+                            uid: response["uid"].as_str().unwrap_or_default().to_string(),
+                            version: response["version"].as_i64().unwrap_or_default(),
+                            folder_id,
+                            hash,
+                        },
+                    );
+                }
+                Action::Delete { title, previous } => {
+                    if let Some(backup) = backup {
+                        if let Some(existing) = client.get_dashboard(&previous.uid)? {
+                            backup.save(&existing)?;
+                        }
+                    }
+                    client.delete_dashboard(&previous.uid)?;
+                    info!("Deleted dashboard \"{}\"", title);
+                    state.dashboards.remove(&title);
+                }
+            }
+            state.save(state_path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Action {
+    fn title(&self) -> &str {
+        match self {
+            Action::Create(dashboard)
+            | Action::Update { dashboard, .. }
+            | Action::Drift { dashboard, .. } => &dashboard.title,
+            Action::Unchanged(title) | Action::Delete { title, .. } => title,
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for action in &self.actions {
+            match action {
+                Action::Create(dashboard) => writeln!(f, "+ create    \"{}\"", dashboard.title)?,
+                Action::Update { previous, .. } => writeln!(
+                    f,
+                    "~ update    \"{}\" (uid {}, version {})",
+                    action.title(),
+                    previous.uid,
+                    previous.version
+                )?,
+                Action::Drift {
+                    previous, version, ..
+                } => writeln!(
+                    f,
+                    "! drift     \"{}\" (uid {}): Grafana has version {}, state expects {}",
+                    action.title(),
+                    previous.uid,
+                    version,
+                    previous.version
+                )?,
+                Action::Unchanged(title) => writeln!(f, "  unchanged \"{}\"", title)?,
+                Action::Delete { title, previous } => {
+                    writeln!(f, "- delete    \"{}\" (uid {})", title, previous.uid)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn dashboard(title: &str) -> grafana::Dashboard {
+        serde_json::from_value(json!({
+            "uid": title,
+            "title": title,
+            "tags": [],
+            "panels": [],
+            "time": {"from": "now-2d", "to": "now"},
+        }))
+        .unwrap()
+    }
+
+    fn previous(dashboard: &grafana::Dashboard, version: i64) -> DashboardState {
+        DashboardState {
+            uid: dashboard.uid.clone(),
+            version,
+            folder_id: 0,
+            hash: dashboard.content_hash(),
+        }
+    }
+
+    #[test]
+    fn plan_creates_updates_and_deletes() {
+        let unchanged = dashboard("unchanged");
+        let mut state = State::default();
+        state
+            .dashboards
+            .insert("unchanged".to_string(), previous(&unchanged, 1));
+        state
+            .dashboards
+            .insert("removed".to_string(), previous(&dashboard("removed"), 1));
+        let mut updated = previous(&dashboard("updated"), 2);
+        updated.hash = "outdated".to_string();
+        state.dashboards.insert("updated".to_string(), updated);
+
+        let plan = Plan::new(
+            vec![unchanged, dashboard("updated"), dashboard("new")],
+            &state,
+            |uid| Ok(Some(if uid == "updated" { 2 } else { 1 })),
+        )
+        .unwrap();
+
+        assert_eq!(
+            "  unchanged \"unchanged\"\n\
+             ~ update    \"updated\" (uid updated, version 2)\n\
+             + create    \"new\"\n\
+             - delete    \"removed\" (uid removed)\n",
+            plan.to_string()
+        );
+        let kept = plan.keep(&["removed".to_string()]);
+        assert!(!kept
+            .actions
+            .iter()
+            .any(|a| matches!(a, Action::Delete { .. })));
+    }
+
+    #[test]
+    fn plan_detects_drift() {
+        let drifted = dashboard("drifted");
+        let mut state = State::default();
+        state
+            .dashboards
+            .insert("drifted".to_string(), previous(&drifted, 3));
+
+        let plan = Plan::new(vec![drifted], &state, |_| Ok(Some(5))).unwrap();
+
+        match &plan.actions[0] {
+            Action::Drift { version, .. } => assert_eq!(5, *version),
+            action => panic!("unexpected action {:?}", action),
+        }
+    }
+
+    #[test]
+    fn plan_recreates_dashboards_deleted_in_grafana() {
+        let deleted = dashboard("deleted");
+        let mut state = State::default();
+        state
+            .dashboards
+            .insert("deleted".to_string(), previous(&deleted, 3));
+
+        let plan = Plan::new(vec![deleted], &state, |_| Ok(None)).unwrap();
+
+        match &plan.actions[0] {
+            Action::Create(_) => {}
+            action => panic!("unexpected action {:?}", action),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::client::GrafanaClient;
+use crate::grafana;
+use crate::state::{DashboardState, State};
+use log::{error, info};
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Keeps Grafana in line with Graylog by converting and pushing dashboards every `interval`
+/// until the process receives Ctrl-C or SIGTERM. A failing cycle or dashboard is logged
+/// and retried in the next cycle. Pushed dashboards are recorded in the state file at
+/// `state_path`, so a restart only pushes what changed in the meantime.
+pub fn run<F>(
+    client: &GrafanaClient,
+    interval: Duration,
+    state_path: &Path,
+    fetch: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn() -> Result<Vec<grafana::Dashboard>, Box<dyn Error>>,
+{
+    let running = Arc::new(AtomicBool::new(true));
+    let handler = running.clone();
+    ctrlc::set_handler(move || handler.store(false, Ordering::SeqCst))?;
+
+    let mut state = State::load(state_path)?;
+    while running.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        match cycle(client, &fetch, &mut state, state_path) {
+            Ok((updated, failed, total)) => info!(
+                "Sync cycle finished in {:?}: {} of {} dashboards updated, {} failed",
+                started.elapsed(),
+                updated,
+                total,
+                failed
+            ),
+            Err(e) => error!("Sync cycle failed: {}", e),
+        }
+        while running.load(Ordering::SeqCst) && started.elapsed() < interval {
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+    info!("Sync stopped");
+    Ok(())
+}
+
+/// Pushes the changed dashboards, returning how many were updated, failed and fetched.
+fn cycle<F>(
+    client: &GrafanaClient,
+    fetch: &F,
+    state: &mut State,
+    state_path: &Path,
+) -> Result<(usize, usize, usize), Box<dyn Error>>
+where
+    F: Fn() -> Result<Vec<grafana::Dashboard>, Box<dyn Error>>,
+{
+    let dashboards = fetch()?;
+    let total = dashboards.len();
+    let (mut updated, mut failed) = (0, 0);
+    for (dashboard, hash) in changed_dashboards(dashboards, state) {
+        let title = dashboard.title.clone();
+        let folder_id = dashboard.folder_id;
+        let response = match client.post_dashboard(&grafana::ApiDashboard {
+            dashboard,
+            folder_id,
+            overwrite: true,
+        }) {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Updating dashboard \"{}\" failed: {}", title, e);
+                failed += 1;
+                continue;
+            }
+        };
+        info!("Updated dashboard \"{}\"", title);
+        updated += 1;
+        state.dashboards.insert(
+            title,
+            DashboardState {
+                uid: response["uid"].as_str().unwrap_or_default().to_string(),
+                version: response["version"].as_i64().unwrap_or_default(),
+                folder_id,
+                hash,
+            },
+        );
+        state.save(state_path)?;
+    }
+    Ok((updated, failed, total))
+}
+
+/// Dashboards whose content hash differs from the one pushed last, together with the new hash.
+fn changed_dashboards(
+    dashboards: Vec<grafana::Dashboard>,
+    state: &State,
+) -> Vec<(grafana::Dashboard, String)> {
+    dashboards
+        .into_iter()
+        .map(|d| {
+            let hash = d.content_hash();
+            (d, hash)
+        })
+        .filter(|(d, hash)| state.dashboards.get(&d.title).map(|s| &s.hash) != Some(hash))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn dashboard(title: &str, from: &str) -> grafana::Dashboard {
+        serde_json::from_value(json!({
+            "uid": title,
+            "title": title,
+            "tags": [],
+            "panels": [],
+            "time": {"from": from, "to": "now"},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn changed_dashboards_skips_dashboards_with_same_hash() {
+        let mut pushed = State::default();
+        let recorded = |hash| DashboardState {
+            uid: String::new(),
+            version: 1,
+            folder_id: 0,
+            hash,
+        };
+        let same = dashboard("same", "now-2d");
+        pushed
+            .dashboards
+            .insert("same".to_string(), recorded(same.content_hash()));
+        pushed.dashboards.insert(
+            "changed".to_string(),
+            recorded(dashboard("changed", "now-2d").content_hash()),
+        );
+
+        let changed = changed_dashboards(
+            vec![
+                same,
+                dashboard("changed", "now-7d"),
+                dashboard("new", "now-2d"),
+            ],
+            &pushed,
+        );
+
+        let titles: Vec<_> = changed.iter().map(|(d, _)| d.title.as_str()).collect();
+        assert_eq!(vec!["changed", "new"], titles);
+    }
+}
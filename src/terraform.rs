@@ -0,0 +1,240 @@
+use crate::grafana::Dashboard;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Directory below the output directory receiving the dashboard json files.
+pub const DASHBOARDS_DIR: &str = "dashboards";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    /// `main.tf` in the native HCL syntax.
+    Hcl,
+    /// `main.tf.json` in Terraform's JSON syntax.
+    Json,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(syntax: &str) -> Result<Syntax, String> {
+        match syntax {
+            "hcl" => Ok(Syntax::Hcl),
+            "json" => Ok(Syntax::Json),
+            _ => Err(format!("Unknown Terraform syntax {}", syntax)),
+        }
+    }
+}
+
+/// `grafana_folder` and `grafana_dashboard` resources of the Grafana provider.
+#[derive(Debug, Default, PartialEq)]
+pub struct Resources {
+    /// Folder titles by resource name.
+    pub folders: BTreeMap<String, String>,
+    pub dashboards: Vec<DashboardResource>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DashboardResource {
+    pub name: String,
+    /// Dashboard json file relative to the module, named like the resource.
+    pub file: String,
+    /// Resource name of the folder, `None` for the General folder.
+    pub folder: Option<String>,
+}
+
+impl Resources {
+    /// Resource names are derived from the titles, so they stay the same as long as
+    /// the titles do. Clashing names get a numeric suffix in the order of `dashboards`.
+    pub fn new(dashboards: &[Dashboard]) -> Resources {
+        let mut resources = Resources::default();
+        let mut folders = BTreeMap::new();
+        for dashboard in dashboards {
+            let folder = dashboard.folder_title.as_ref().map(|title| {
+                folders
+                    .entry(title.clone())
+                    .or_insert_with(|| {
+                        let name = unique_name(resource_name(title), |name| {
+                            resources.folders.contains_key(name)
+                        });
+                        resources.folders.insert(name.clone(), title.clone());
+                        name
+                    })
+                    .clone()
+            });
+            let name = unique_name(resource_name(&dashboard.title), |name| {
+                resources.dashboards.iter().any(|d| d.name == name)
+            });
+            resources.dashboards.push(DashboardResource {
+                file: format!("{}/{}.json", DASHBOARDS_DIR, name),
+                name,
+                folder,
+            });
+        }
+        resources
+    }
+
+    pub fn to_hcl(&self) -> String {
+        let mut hcl = String::new();
+        for (name, title) in &self.folders {
+            let _ = write!(
+                hcl,
+                "resource \"grafana_folder\" \"{}\" {{\n  title = {}\n}}\n\n",
+                name,
+                hcl_string(title)
+            );
+        }
+        for dashboard in &self.dashboards {
+            let _ = writeln!(
+                hcl,
+                "resource \"grafana_dashboard\" \"{}\" {{",
+                dashboard.name
+            );
+            if let Some(folder) = &dashboard.folder {
+                let _ = writeln!(hcl, "  folder      = grafana_folder.{}.uid", folder);
+            }
+            let _ = write!(
+                hcl,
+                "  config_json = file(\"${{path.module}}/{}\")\n  overwrite   = true\n}}\n\n",
+                dashboard.file
+            );
+        }
+        hcl.pop();
+        hcl
+    }
+
+    pub fn to_json(&self) -> Value {
+        let folders: Map<String, Value> = self
+            .folders
+            .iter()
+            .map(|(name, title)| (name.clone(), json!({ "title": escape_template(title) })))
+            .collect();
+        let dashboards: Map<String, Value> = self
+            .dashboards
+            .iter()
+            .map(|dashboard| {
+                let mut resource = json!({
+                    "config_json": format!("${{file(\"${{path.module}}/{}\")}}", dashboard.file),
+                    "overwrite": true,
+                });
+                if let Some(folder) = &dashboard.folder {
+                    resource["folder"] = json!(format!("${{grafana_folder.{}.uid}}", folder));
+                }
+                (dashboard.name.clone(), resource)
+            })
+            .collect();
+        let mut resource = Map::new();
+        if !folders.is_empty() {
+            resource.insert("grafana_folder".to_string(), Value::Object(folders));
+        }
+        resource.insert("grafana_dashboard".to_string(), Value::Object(dashboards));
+        json!({ "resource": resource })
+    }
+}
+
+/// Writes the dashboard json files and `main.tf` or `main.tf.json` referencing them into `dir`.
+pub fn write(dir: &Path, dashboards: &[Dashboard], syntax: Syntax) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir.join(DASHBOARDS_DIR))?;
+    let resources = Resources::new(dashboards);
+    for (dashboard, resource) in dashboards.iter().zip(&resources.dashboards) {
+        let file = File::create(dir.join(&resource.file))?;
+        serde_json::to_writer_pretty(file, dashboard)?;
+    }
+    match syntax {
+        Syntax::Hcl => write!(File::create(dir.join("main.tf"))?, "{}", resources.to_hcl())?,
+        Syntax::Json => serde_json::to_writer_pretty(
+            File::create(dir.join("main.tf.json"))?,
+            &resources.to_json(),
+        )?,
+    }
+    Ok(())
+}
+
+/// Terraform names consist of letters, digits, underscores and dashes and start with a letter
+/// or underscore.
+fn resource_name(title: &str) -> String {
+    let mut name = String::new();
+    for c in title.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_end_matches('_');
+    match name.chars().next() {
+        None => "dashboard".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("_{}", name),
+        Some(_) => name.to_string(),
+    }
+}
+
+fn unique_name<F: Fn(&str) -> bool>(name: String, taken: F) -> String {
+    if !taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|i| format!("{}_{}", name, i))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+/// Strings are templates in both syntaxes, where `${` and `%{` start interpolations.
+fn escape_template(value: &str) -> String {
+    value.replace("${", "$${").replace("%{", "%%{")
+}
+
+fn hcl_string(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escape_template(&escaped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_stable_and_unique() {
+        let dashboards = vec![
+            Dashboard::new("Web Errors").with_folder_title("Team A"),
+            Dashboard::new("web/errors").with_folder_title("Team A"),
+            Dashboard::new("5xx"),
+        ];
+
+        let resources = Resources::new(&dashboards);
+
+        assert_eq!(
+            vec!["web_errors", "web_errors_2", "_5xx"],
+            resources
+                .dashboards
+                .iter()
+                .map(|d| d.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("dashboards/web_errors_2.json", resources.dashboards[1].file);
+        assert_eq!(Some("team_a"), resources.dashboards[1].folder.as_deref());
+        assert_eq!(1, resources.folders.len());
+    }
+
+    #[test]
+    fn render_hcl() {
+        let dashboards = vec![Dashboard::new("Web Errors").with_folder_title("${team}")];
+
+        assert_eq!(
+            "resource \"grafana_folder\" \"team\" {\n  title = \"$${team}\"\n}\n\n\
+             resource \"grafana_dashboard\" \"web_errors\" {\n  \
+             folder      = grafana_folder.team.uid\n  \
+             config_json = file(\"${path.module}/dashboards/web_errors.json\")\n  \
+             overwrite   = true\n}\n",
+            Resources::new(&dashboards).to_hcl()
+        );
+    }
+}
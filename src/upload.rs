@@ -0,0 +1,190 @@
+use crate::backup::Backup;
+use crate::client::GrafanaClient;
+use crate::dashboard_v2::{self, ResourceOptions};
+use crate::export::ImportRequest;
+use crate::grafana::ApiDashboard;
+use log::{error, info};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::thread;
+
+/// API the dashboards are uploaded through.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadApi {
+    /// `/api/dashboards/db`
+    Dashboards,
+    /// `/api/dashboards/import`, in the format for sharing.
+    Import,
+    /// Resource API of v2 dashboards, in the namespace of the organisation.
+    ResourceV2 { namespace: String },
+}
+
+/// Uploads dashboards with up to `concurrency` requests at a time, backing up the ones which
+/// get overwritten first. A failed dashboard doesn't stop the others, all failures are
+/// reported together at the end.
+pub fn upload(
+    client: &GrafanaClient,
+    dashboards: Vec<ApiDashboard>,
+    backup: Option<&Backup>,
+    concurrency: usize,
+    api: &UploadApi,
+) -> Result<(), Box<dyn Error>> {
+    let total = dashboards.len();
+    let references = match api {
+        UploadApi::ResourceV2 { .. } => References::resolve(client, &dashboards)?,
+        _ => References::default(),
+    };
+    let failed = Mutex::new(vec![]);
+    for_each_concurrent(dashboards, concurrency, |dashboard| {
+        let title = dashboard.dashboard.title.clone();
+        match upload_dashboard(client, &dashboard, backup, api, &references) {
+            Ok(()) => info!("Uploaded dashboard \"{}\"", title),
+            Err(e) => {
+                error!("Uploading dashboard \"{}\" failed: {}", title, e);
+                failed.lock().unwrap().push(title);
+            }
+        }
+    });
+    let failed = failed.into_inner().unwrap();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} of {} dashboards failed to upload: {}",
+            failed.len(),
+            total,
+            failed.join(", ")
+        )
+        .into())
+    }
+}
+
+fn upload_dashboard(
+    client: &GrafanaClient,
+    dashboard: &ApiDashboard,
+    backup: Option<&Backup>,
+    api: &UploadApi,
+    references: &References,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(backup) = backup {
+        if let Some(existing) = client.find_dashboard(&dashboard.dashboard, dashboard.folder_id)? {
+            backup.save(&existing)?;
+        }
+    }
+    match api {
+        UploadApi::Dashboards => {
+            client.post_dashboard(dashboard)?;
+        }
+        UploadApi::Import => {
+            client.import_dashboard(&ImportRequest::new(
+                &dashboard.dashboard,
+                dashboard.folder_id,
+            ))?;
+        }
+        UploadApi::ResourceV2 { namespace } => {
+            let opt = ResourceOptions {
+                namespace: Some(namespace.clone()),
+                folder_uid: references.folder_uids.get(&dashboard.folder_id).cloned(),
+                datasource_uids: references.datasource_uids.clone(),
+            };
+            client.put_dashboard_v2(
+                namespace,
+                &dashboard_v2::resource(&dashboard.dashboard, &opt)?,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Uids of the folders and datasources v2 dashboards reference, looked up once before
+/// the upload.
+#[derive(Debug, Default)]
+struct References {
+    folder_uids: HashMap<i64, String>,
+    datasource_uids: HashMap<String, String>,
+}
+
+impl References {
+    fn resolve(
+        client: &GrafanaClient,
+        dashboards: &[ApiDashboard],
+    ) -> Result<References, Box<dyn Error>> {
+        let mut references = References::default();
+        for dashboard in dashboards {
+            if dashboard.folder_id != 0
+                && !references.folder_uids.contains_key(&dashboard.folder_id)
+            {
+                let uid = client.get_folder_uid(dashboard.folder_id)?;
+                references.folder_uids.insert(dashboard.folder_id, uid);
+            }
+            let datasources = dashboard
+                .dashboard
+                .panels
+                .iter()
+                .map(|panel| &panel.datasource)
+                .chain(
+                    dashboard
+                        .dashboard
+                        .annotations
+                        .list
+                        .iter()
+                        .map(|a| &a.datasource),
+                );
+            for datasource in datasources {
+                if !references.datasource_uids.contains_key(datasource) {
+                    let uid = client.get_datasource_uid(datasource)?;
+                    references.datasource_uids.insert(datasource.clone(), uid);
+                }
+            }
+        }
+        Ok(references)
+    }
+}
+
+/// Calls `f` for every item on `concurrency` threads, returning when all items are done.
+fn for_each_concurrent<T, F>(items: Vec<T>, concurrency: usize, f: F)
+where
+    T: Send,
+    F: Fn(T) + Sync,
+{
+    let queue = Mutex::new(items.into_iter());
+    thread::scope(|scope| {
+        for _ in 0..concurrency.max(1) {
+            scope.spawn(|| loop {
+                let item = queue.lock().unwrap().next();
+                match item {
+                    Some(item) => f(item),
+                    None => break,
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn for_each_concurrent_bounds_running_calls() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let done = Mutex::new(vec![]);
+
+        for_each_concurrent((0..20).collect(), 3, |i| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+            done.lock().unwrap().push(i);
+        });
+
+        let mut done = done.into_inner().unwrap();
+        done.sort();
+        assert_eq!((0..20).collect::<Vec<_>>(), done);
+        assert!(max_running.load(Ordering::SeqCst) <= 3);
+    }
+}
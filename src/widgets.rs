@@ -0,0 +1,301 @@
+use crate::graylog;
+use crate::ir::{
+    Bucket, ChartStyle, Layout, MetricFunction, Panel, Query, Visualization, WarningKind,
+};
+use log::warn;
+use std::collections::HashMap;
+use std::error::Error;
+
+pub const SEARCH_RESULT_COUNT: &str = "SEARCH_RESULT_COUNT";
+pub const SEARCH_RESULT_CHART: &str = "SEARCH_RESULT_CHART";
+pub const QUICKVALUES: &str = "QUICKVALUES";
+pub const FIELD_CHART: &str = "FIELD_CHART";
+pub const STACKED_CHART: &str = "STACKED_CHART";
+
+/// Parses one type of Graylog widget into a panel of the intermediate representation.
+///
+/// Implemented for all functions with a matching signature, so closures can be registered directly.
+pub trait WidgetConverter {
+    /// Returns `None` to leave the widget out of the dashboard.
+    fn convert(&self, widget: graylog::DashboardWidget) -> Result<Option<Panel>, Box<dyn Error>>;
+}
+
+impl<F> WidgetConverter for F
+where
+    F: Fn(graylog::DashboardWidget) -> Result<Option<Panel>, Box<dyn Error>>,
+{
+    fn convert(&self, widget: graylog::DashboardWidget) -> Result<Option<Panel>, Box<dyn Error>> {
+        self(widget)
+    }
+}
+
+/// Widget converters keyed by Graylog widget type, starting out with the built-in ones.
+pub struct WidgetConverterRegistry {
+    converters: HashMap<String, Box<dyn WidgetConverter>>,
+}
+
+impl Default for WidgetConverterRegistry {
+    fn default() -> WidgetConverterRegistry {
+        let mut registry = WidgetConverterRegistry {
+            converters: HashMap::new(),
+        };
+        registry.register(FIELD_CHART, field_chart);
+        registry.register(STACKED_CHART, stacked_chart);
+        registry.register(SEARCH_RESULT_COUNT, search_result_count);
+        registry.register(SEARCH_RESULT_CHART, search_result_chart);
+        registry.register(QUICKVALUES, quick_values);
+        registry
+    }
+}
+
+impl WidgetConverterRegistry {
+    /// Registers `converter` for `widget_type`, replacing the previous converter of that type.
+    pub fn register<C>(&mut self, widget_type: &str, converter: C)
+    where
+        C: WidgetConverter + 'static,
+    {
+        self.converters
+            .insert(widget_type.to_string(), Box::new(converter));
+    }
+
+    pub fn is_registered(&self, widget_type: &str) -> bool {
+        self.converters.contains_key(widget_type)
+    }
+
+    /// Converts a widget with the converter registered for its type. The panel is placed
+    /// where the widget was on the Graylog dashboard. Widgets of unknown types are left out.
+    pub fn convert(
+        &self,
+        widget: graylog::DashboardWidget,
+    ) -> Result<Option<Panel>, Box<dyn Error>> {
+        let converter = match self.converters.get(&widget.r#type) {
+            Some(converter) => converter,
+            None => {
+                warn!(
+                    "Not Supported {} graph: {}",
+                    widget.r#type, widget.description
+                );
+                return Ok(None);
+            }
+        };
+        let layout = Layout::new_with_widget(&widget);
+        let source_type = widget.r#type.clone();
+        Ok(converter.convert(widget)?.map(|panel| Panel {
+            source_type: Some(source_type),
+            ..panel.with_layout(layout)
+        }))
+    }
+}
+
+fn required<T>(value: Option<T>, widget: &str, name: &str) -> Result<T, Box<dyn Error>> {
+    value.ok_or_else(|| format!("Widget \"{}\" has no {}", widget, name).into())
+}
+
+/// Function computing a Graylog `valuetype`, `None` for valuetypes without Elasticsearch
+/// metric of their own like `std_deviation` or `variance`.
+fn exact_metric_function(valuetype: &str) -> Option<MetricFunction> {
+    match valuetype {
+        "count" => Some(MetricFunction::Count),
+        "total" | "sum" => Some(MetricFunction::Sum),
+        "mean" => Some(MetricFunction::Avg),
+        "min" => Some(MetricFunction::Min),
+        "max" => Some(MetricFunction::Max),
+        "cardinality" => Some(MetricFunction::Cardinality),
+        _ => None,
+    }
+}
+
+/// Function for a Graylog `valuetype`, valuetypes without equivalent sum `field`.
+fn metric_function(valuetype: &str) -> MetricFunction {
+    exact_metric_function(valuetype).unwrap_or(MetricFunction::Sum)
+}
+
+/// Warns about valuetypes `metric_function` replaces by a sum.
+fn approximate_metric(panel: Panel, valuetype: &str) -> Panel {
+    match exact_metric_function(valuetype) {
+        Some(_) => panel,
+        None => panel.with_warning(
+            WarningKind::ApproximatedMetric,
+            &format!("{} approximated as sum", valuetype),
+        ),
+    }
+}
+
+fn chart_style(renderer: graylog::ChartRenderer) -> ChartStyle {
+    match renderer {
+        graylog::ChartRenderer::Bar => ChartStyle::Bar,
+        graylog::ChartRenderer::Line => ChartStyle::Line,
+        graylog::ChartRenderer::Area => ChartStyle::Area,
+    }
+}
+
+fn field_chart(widget: graylog::DashboardWidget) -> Result<Option<Panel>, Box<dyn Error>> {
+    let title = &widget.description;
+    let configuration = widget.configuration;
+    let query = required(configuration.query, title, "query")?;
+    let valuetype = required(configuration.valuetype, title, "valuetype")?;
+    let query = Query::new_count(&query)
+        .with_metric(
+            metric_function(&valuetype),
+            Some(required(configuration.field, title, "field")?),
+        )
+        .with_bucket(Bucket::DateHistogram {
+            interval: required(configuration.interval, title, "interval")?.grafana(),
+        });
+    let style = chart_style(required(configuration.renderer, title, "renderer")?);
+    let panel = Panel::new(title, Visualization::Chart { style })
+        .with_query(query)
+        .with_time_range(configuration.timerange.range);
+    Ok(Some(approximate_metric(panel, &valuetype)))
+}
+
+fn stacked_chart(widget: graylog::DashboardWidget) -> Result<Option<Panel>, Box<dyn Error>> {
+    let title = &widget.description;
+    let configuration = widget.configuration;
+    let interval = required(configuration.interval, title, "interval")?.grafana();
+    let style = chart_style(required(configuration.renderer, title, "renderer")?);
+    let series = required(configuration.series, title, "series")?;
+    let panel = series
+        .iter()
+        .map(|s| {
+            Query::new_count(&s.query)
+                .with_metric(
+                    metric_function(&s.statistical_function),
+                    Some(s.field.clone()),
+                )
+                .with_bucket(Bucket::DateHistogram {
+                    interval: interval.clone(),
+                })
+        })
+        .fold(
+            Panel::new(title, Visualization::Chart { style }),
+            Panel::with_query,
+        )
+        .with_time_range(configuration.timerange.range);
+    let panel = series.iter().fold(panel, |panel, s| {
+        approximate_metric(panel, &s.statistical_function)
+    });
+    Ok(Some(panel))
+}
+
+fn search_result_count(widget: graylog::DashboardWidget) -> Result<Option<Panel>, Box<dyn Error>> {
+    let title = &widget.description;
+    let configuration = widget.configuration;
+    let trend = required(configuration.trend, title, "trend")?;
+    let panel = Panel::new(title, Visualization::SingleValue { trend })
+        .with_query(
+            Query::new_count(&required(configuration.query, title, "query")?).with_bucket(
+                Bucket::DateHistogram {
+                    interval: "1m".to_string(),
+                },
+            ),
+        )
+        .with_time_range(configuration.timerange.range);
+    Ok(Some(panel))
+}
+
+fn search_result_chart(widget: graylog::DashboardWidget) -> Result<Option<Panel>, Box<dyn Error>> {
+    let title = &widget.description;
+    let configuration = widget.configuration;
+    let query = Query::new_count(&required(configuration.query, title, "query")?).with_bucket(
+        Bucket::DateHistogram {
+            interval: required(configuration.interval, title, "interval")?.grafana(),
+        },
+    );
+    let panel = Panel::new(
+        title,
+        Visualization::Chart {
+            style: ChartStyle::Bar,
+        },
+    )
+    .with_query(query)
+    .with_time_range(configuration.timerange.range);
+    Ok(Some(panel))
+}
+
+fn quick_values(widget: graylog::DashboardWidget) -> Result<Option<Panel>, Box<dyn Error>> {
+    let title = &widget.description;
+    let configuration = widget.configuration;
+    let query = Query::new_count(&required(configuration.query, title, "query")?).with_bucket(
+        Bucket::Terms {
+            field: required(configuration.field, title, "field")?,
+            order: configuration
+                .sort_order
+                .unwrap_or_else(|| "desc".to_string()),
+            size: configuration.limit.unwrap_or(5),
+        },
+    );
+    let panel = Panel::new(title, Visualization::Pie)
+        .with_query(query)
+        .with_time_range(configuration.timerange.range);
+    Ok(Some(panel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn widget(r#type: &str, configuration: serde_json::Value) -> graylog::DashboardWidget {
+        serde_json::from_value(json!({
+            "description": "Errors",
+            "type": r#type,
+            "configuration": configuration,
+            "row": 2, "col": 1, "height": 1, "width": 2,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn missing_configuration_fails() {
+        let registry = WidgetConverterRegistry::default();
+
+        let error = registry
+            .convert(widget(FIELD_CHART, json!({"timerange": {"range": 300}})))
+            .unwrap_err();
+
+        assert_eq!("Widget \"Errors\" has no query", error.to_string());
+    }
+
+    #[test]
+    fn valuetypes_become_metric_functions() {
+        let registry = WidgetConverterRegistry::default();
+        let chart = |valuetype: &str| {
+            widget(
+                FIELD_CHART,
+                json!({
+                    "query": "*", "field": "took_ms", "valuetype": valuetype,
+                    "interval": "minute", "renderer": "line", "timerange": {"range": 300},
+                }),
+            )
+        };
+
+        let mean = registry.convert(chart("mean")).unwrap().unwrap();
+        let deviation = registry.convert(chart("std_deviation")).unwrap().unwrap();
+
+        assert_eq!(MetricFunction::Avg, mean.queries[0].metric.function);
+        assert!(mean.warnings.is_empty());
+        assert_eq!(MetricFunction::Sum, deviation.queries[0].metric.function);
+        assert_eq!(
+            "std_deviation approximated as sum",
+            deviation.warnings[0].message
+        );
+    }
+
+    #[test]
+    fn custom_converters_handle_plugin_widgets() {
+        let mut registry = WidgetConverterRegistry::default();
+        let plugin = widget("ORG_HEATMAP", json!({"query": "level:3", "bins": 10}));
+        assert!(registry.convert(plugin.clone()).unwrap().is_none());
+
+        registry.register("ORG_HEATMAP", |widget: graylog::DashboardWidget| {
+            let bins = widget.configuration.other["bins"].to_string();
+            Ok(Some(Panel::new(&bins, Visualization::Pie)))
+        });
+        let panel = registry.convert(plugin).unwrap().unwrap();
+
+        assert_eq!("10", panel.title);
+        assert_eq!(2, panel.layout.row);
+        assert_eq!(2, panel.layout.width);
+    }
+}